@@ -1,81 +1,109 @@
+use std::{fmt::Debug, io};
+
 use esp_idf_hal::io::Read;
-use link_board::{data_retriever::DataRetriever, display::Route, env};
+use link_board::{
+    config::Config,
+    data_retriever::{check_status, DataRetriever},
+    error::Error,
+    retry::{retry, RetryPolicy}
+};
 use embedded_svc::http::{client::Client, Method};
 use esp_idf_svc::http::client::{Configuration, EspHttpConnection};
 
-#[derive(Default)]
-pub struct DataRetrieverImpl;
+pub struct DataRetrieverImpl {
+    config: Config,
+}
 
-pub fn get_data_retriever() -> DataRetrieverImpl {
-    DataRetrieverImpl::default()
+pub fn get_data_retriever(config: &Config) -> DataRetrieverImpl {
+    DataRetrieverImpl::new(config)
 }
 
 impl DataRetrieverImpl {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
 }
 
 impl DataRetriever for DataRetrieverImpl {
     async fn get_json_for_all_trains(&self) -> Result<Vec<(link_board::display::Route, String)>, link_board::error::Error> {
         // much of this code is from https://github.com/esp-rs/std-training/blob/main/intro/http-client/examples/https_client.rs
-        let routes = vec![Route::Line1, Route::Line2];
+        let routes = self.config.routes();
         log::info!("retrieving {} route(s)", routes.len());
-        let urls = routes.into_iter().map(|route| (route, DataRetrieverImpl::url_for_route(route, env::api_key())));
+        let urls = routes.iter().map(|&route| (route, DataRetrieverImpl::url_for_route(route, &self.config)));
         log::info!("got {} url(s)", urls.len());
         let mut results = Vec::with_capacity(urls.len());
+        let policy = RetryPolicy::new(&self.config);
 
         let connection = EspHttpConnection::new(&Configuration {
             use_global_ca_store: true,
             crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
+            timeout: Some(policy.timeout),
             ..Default::default()
-        }).unwrap();
+        }).map_err(esp_error)?;
         log::info!("got a connection");
 
         let mut client = Client::wrap(connection);
         log::info!("got a client");
-        let headers = [("accept", "text/plain")];
 
         for (current_route, current_url) in urls {
-            let mut result_json = String::new();
-            let request = client.request(Method::Get, &current_url.as_ref(), &headers).unwrap();
-            log::info!("submitting request...");
-            let response = request.submit().unwrap();
-            let status = response.status();
-
-            log::info!("response code: {}", status);
-
-            match status {
-                200..=299 => {
-                    let mut buf = [0_u8; 2048];
-                    let mut offset = 0;
-                    let mut total = 0;
-                    let mut reader = response;
-                    loop {
-                        if let Ok(size) = Read::read(&mut reader, &mut buf[offset..]) {
-                            if size == 0 {
-                                break;
-                            }
-                            total += size;
-                            log::info!("reading {} bytes, current total {} bytes", size, total);
-                            let size_plus_offset = size + offset;
-                            match std::str::from_utf8(&buf[..size_plus_offset]) {
-                                Ok(text) => {
-                                    result_json.push_str(text);
-                                    offset = 0;
-                                }
-                                Err(error) => {
-                                    let valid_up_to = error.valid_up_to();
-                                    buf.copy_within(valid_up_to.., 0);
-                                    offset = size_plus_offset - valid_up_to;
-                                }
-                            }
-                        }
-                    }
-                    log::info!("Total: {} bytes", total);
-                    results.push((current_route, result_json));
+            // the client blocks, so the fetch and the wait between retries happen right away
+            let result_json = retry(
+                &policy,
+                || std::future::ready(fetch(&mut client, &current_url)),
+                |delay| {
+                    std::thread::sleep(delay);
+                    std::future::ready(())
                 },
-                _ => log::error!("unexpected response code: {}", status)
-            }
+            ).await?;
+            results.push((current_route, result_json));
         }
 
         Ok(results)
     }
-}
\ No newline at end of file
+}
+
+fn fetch(client: &mut Client<EspHttpConnection>, url: &str) -> Result<String, Error> {
+    let headers = [("accept", "text/plain")];
+    let mut result_json = String::new();
+    let request = client.request(Method::Get, url, &headers).map_err(esp_error)?;
+    log::info!("submitting request...");
+    let response = request.submit().map_err(esp_error)?;
+    let status = response.status();
+    let retry_after = response.header("Retry-After").map(str::to_string);
+
+    log::info!("response code: {}", status);
+
+    let mut buf = [0_u8; 2048];
+    let mut offset = 0;
+    let mut total = 0;
+    let mut reader = response;
+    loop {
+        let size = Read::read(&mut reader, &mut buf[offset..]).map_err(esp_error)?;
+        if size == 0 {
+            break;
+        }
+        total += size;
+        log::info!("reading {} bytes, current total {} bytes", size, total);
+        let size_plus_offset = size + offset;
+        match std::str::from_utf8(&buf[..size_plus_offset]) {
+            Ok(text) => {
+                result_json.push_str(text);
+                offset = 0;
+            }
+            Err(error) => {
+                let valid_up_to = error.valid_up_to();
+                buf.copy_within(valid_up_to.., 0);
+                offset = size_plus_offset - valid_up_to;
+            }
+        }
+    }
+    log::info!("Total: {} bytes", total);
+    check_status(status, retry_after.as_deref(), result_json)
+}
+
+/// HTTP client errors are treated like I/O errors, so they are retried.
+fn esp_error(e: impl Debug) -> Error {
+    Error::io_error(io::Error::other(format!("{e:?}")))
+}
@@ -1,9 +1,11 @@
-use anyhow::{Ok, Result};
+use std::{sync::{Arc, Mutex}, thread, time::{Duration, Instant}};
+
+use anyhow::Result;
 use data_retriever::get_data_retriever;
 use dotenvy_macro::dotenv;
 use esp_idf_hal::interrupt::IsrCriticalSection;
-use esp_idf_svc::{eventloop::EspSystemEventLoop, hal::{delay, prelude::Peripherals}};
-use link_board::display;
+use esp_idf_svc::{eventloop::EspSystemEventLoop, hal::{delay, prelude::Peripherals}, sntp::EspSntp};
+use link_board::{config::{self, Config}, data_retriever::TrainSource, display::{self, Status, TrainCache}};
 use spi_adapter::spi::SpiAdapter;
 use wifi::wifi;
 
@@ -11,7 +13,11 @@ mod spi_adapter;
 mod data_retriever;
 mod wifi;
 
-const LOOP_PAUSE: u32 = 60000;
+const LOOP_PAUSE: Duration = Duration::from_secs(60);
+/// How often trains are moved along between fetches.
+const RENDER_TICK_MS: u32 = 1000;
+/// The fetch thread parses the JSON and runs TLS, so it needs more than the default pthread stack.
+const FETCH_STACK_SIZE: usize = 16 * 1024;
 
 static CS: IsrCriticalSection = IsrCriticalSection::new();
 
@@ -35,7 +41,6 @@ fn main() -> Result<()> {
         )
     })?;
 
-    let mut i: u64 = 0;
     let spi_adapter = SpiAdapter::new(
         peripherals.spi2,
         peripherals.pins.gpio14,
@@ -43,33 +48,78 @@ fn main() -> Result<()> {
         peripherals.pins.gpio13
     );
 
-    let mut display = display::get_display(spi_adapter);
-    let data_retriever = get_data_retriever();
+    // there is no filesystem or environment on the chip, so the config is baked in from `.env`,
+    // and optional values from the environment at build time
+    let config = Config::from_sources(None, |key| match key {
+        config::API_KEY_VAR => Some(dotenv!("ONEBUSAWAY_API_KEY").to_string()),
+        config::STATIONS_ONLY_VAR => Some(dotenv!("STATIONS_ONLY").to_string()),
+        config::DISPLAY_TYPE_VAR => Some(dotenv!("LINK_BOARD_DISPLAY_TYPE").to_string()),
+        config::OBA_BASE_URL_VAR => option_env!("ONEBUSAWAY_BASE_URL").map(str::to_string),
+        config::ROUTES_VAR => option_env!("LINK_BOARD_ROUTES").map(str::to_string),
+        config::LINE_1_ROUTE_ID_VAR => option_env!("LINK_BOARD_LINE_1_ROUTE_ID").map(str::to_string),
+        config::LINE_2_ROUTE_ID_VAR => option_env!("LINK_BOARD_LINE_2_ROUTE_ID").map(str::to_string),
+        config::REQUEST_TIMEOUT_MS_VAR => option_env!("LINK_BOARD_REQUEST_TIMEOUT_MS").map(str::to_string),
+        config::MAX_RETRIES_VAR => option_env!("LINK_BOARD_MAX_RETRIES").map(str::to_string),
+        config::RETRY_DELAY_MS_VAR => option_env!("LINK_BOARD_RETRY_DELAY_MS").map(str::to_string),
+        config::GRACE_PERIOD_SECS_VAR => option_env!("LINK_BOARD_GRACE_PERIOD_SECS").map(str::to_string),
+        config::NO_DATA_AFTER_SECS_VAR => option_env!("LINK_BOARD_NO_DATA_AFTER_SECS").map(str::to_string),
+        config::MAX_POSITION_AGE_SECS_VAR => option_env!("LINK_BOARD_MAX_POSITION_AGE_SECS").map(str::to_string),
+        config::DROP_OLD_POSITIONS_VAR => option_env!("LINK_BOARD_DROP_OLD_POSITIONS").map(str::to_string),
+        // schedules make the responses too big for the heap on most chips
+        config::INCLUDE_SCHEDULE_VAR => Some(option_env!("LINK_BOARD_INCLUDE_SCHEDULE").unwrap_or("false").to_string()),
+        config::GEO_PLACEMENT_VAR => option_env!("LINK_BOARD_GEO_PLACEMENT").map(str::to_string),
+        config::MERGE_LINE_2_VAR => option_env!("LINK_BOARD_MERGE_LINE_2").map(str::to_string),
+        config::BRIGHTNESS_VAR => option_env!("LINK_BOARD_BRIGHTNESS").map(str::to_string),
+        config::GAMMA_VAR => option_env!("LINK_BOARD_GAMMA").map(str::to_string),
+        config::NIGHT_HOURS_VAR => option_env!("LINK_BOARD_NIGHT_HOURS").map(str::to_string),
+        config::NIGHT_BRIGHTNESS_VAR => option_env!("LINK_BOARD_NIGHT_BRIGHTNESS").map(str::to_string),
+        config::QUIET_HOURS_VAR => option_env!("LINK_BOARD_QUIET_HOURS").map(str::to_string),
+        config::UTC_OFFSET_HOURS_VAR => option_env!("LINK_BOARD_UTC_OFFSET_HOURS").map(str::to_string),
+        config::DAYLIGHT_SAVING_VAR => option_env!("LINK_BOARD_DAYLIGHT_SAVING").map(str::to_string),
+        config::THEME_VAR => option_env!("LINK_BOARD_THEME").map(str::to_string),
+        _ => None,
+    }).map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    let mut display = display::get_display(spi_adapter, &config).map_err(|e| anyhow::anyhow!("{e}"))?;
+    let data_retriever = get_data_retriever(&config);
+    let cache = Arc::new(Mutex::new(TrainCache::new(&config).with_position_placement(display.places_by_position())));
 
     let sysloop = EspSystemEventLoop::take()?;
     let wifi_ssid = dotenv!("WIFI_SSID");
     let password = dotenv!("WIFI_PASSWORD");
 
+    // if connecting fails, the Wi-Fi pattern stays up
+    if let Err(e) = display.show_status(Status::ConnectingWifi) {
+        log::error!("failed to show status: {e}");
+    }
     let _wifi = wifi(
         wifi_ssid,
         password,
         peripherals.modem,
         sysloop,
     )?;
+    // the clock starts at the epoch, and the night and quiet hours are ignored until this sets it
+    let _sntp = EspSntp::new_default()?;
 
-    let delay = delay::Delay::new_default();
-
-    smol::block_on(async {
+    // fetching blocks for as long as the requests take, so it gets its own thread and the LEDs
+    // keep moving on this one
+    let fetch_cache = cache.clone();
+    thread::Builder::new().stack_size(FETCH_STACK_SIZE).spawn(move || {
+        let mut i: u64 = 0;
         loop {
             log::info!("loop {}", i);
-            
-            display::render_trains(&mut display, &data_retriever).await;
-            
+            let result = smol::block_on(data_retriever.get_all_trains());
+            fetch_cache.lock().unwrap().update(result);
+
             log::info!("sleeping...");
-            delay.delay_ms(LOOP_PAUSE);
+            thread::sleep(LOOP_PAUSE);
             i += 1;
         }
-    });
+    })?;
 
-    Ok(())
+    let delay = delay::Delay::new_default();
+    loop {
+        display::render_cached_trains(&mut display, &cache.lock().unwrap(), Instant::now());
+        delay.delay_ms(RENDER_TICK_MS);
+    }
 }
@@ -0,0 +1,333 @@
+use std::fmt;
+
+use crate::{
+    constants::LED_OFF,
+    display::Route,
+    layout::{GeoLed, MapLayout, Side, StationLeds},
+    led::Led,
+    stations
+};
+
+pub const HELP: &str = "\
+commands:
+  n, <enter>            next LED
+  p                     previous LED
+  +N / -N               move forward/back N LEDs
+  g N                   go to LED N
+  s N                   light N LEDs from the current one (0 for a single LED)
+  t LINE SIDE COUNT NAME
+                        tag the current LED as station NAME on LINE (1 or 2), SIDE (s or n),
+                        with COUNT LEDs since the previous stop, e.g. `t 1 s 3 Star Lake`
+  u LINE SIDE NAME      remove a tag
+  l                     list tagged stations
+  w                     write the layout file
+  q                     quit
+  h                     show this help";
+
+const CURSOR_LED: Led = Led::orange();
+const SEGMENT_LED: Led = Led::dull_orange();
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Next,
+    Previous,
+    Move(isize),
+    GoTo(usize),
+    Segment(usize),
+    Tag { route: Route, side: Side, leds_since_prev: usize, station: String },
+    Untag { route: Route, side: Side, station: String },
+    List,
+    Write,
+    Quit,
+    Help,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (cmd, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match cmd {
+            "" | "n" => Ok(Command::Next),
+            "p" => Ok(Command::Previous),
+            "g" => Ok(Command::GoTo(parse_num(rest)?)),
+            "s" => Ok(Command::Segment(parse_num(rest)?)),
+            "l" => Ok(Command::List),
+            "w" => Ok(Command::Write),
+            "q" => Ok(Command::Quit),
+            "h" | "?" => Ok(Command::Help),
+            "t" => {
+                let mut parts = rest.splitn(4, char::is_whitespace);
+                let route = parse_route(parts.next())?;
+                let side = parse_side(parts.next())?;
+                let leds_since_prev = parse_num(parts.next().unwrap_or_default())?;
+                let station = parse_station(parts.next())?;
+                Ok(Command::Tag { route, side, leds_since_prev, station })
+            },
+            "u" => {
+                let mut parts = rest.splitn(3, char::is_whitespace);
+                let route = parse_route(parts.next())?;
+                let side = parse_side(parts.next())?;
+                let station = parse_station(parts.next())?;
+                Ok(Command::Untag { route, side, station })
+            },
+            _ if cmd.starts_with('+') || cmd.starts_with('-') => {
+                cmd.parse().map(Command::Move).map_err(|_| format!("invalid step {cmd:?}"))
+            },
+            _ => Err(format!("unknown command {cmd:?}, `h` for help")),
+        }
+    }
+}
+
+fn parse_num(s: &str) -> Result<usize, String> {
+    s.trim().parse().map_err(|_| format!("expected a number, got {s:?}"))
+}
+
+fn parse_route(s: Option<&str>) -> Result<Route, String> {
+    s.and_then(|s| s.parse::<u8>().ok())
+        .and_then(|line| Route::try_from(line).ok())
+        .ok_or_else(|| format!("expected line 1 or 2, got {s:?}"))
+}
+
+fn parse_side(s: Option<&str>) -> Result<Side, String> {
+    match s {
+        Some("s") => Ok(Side::South),
+        Some("n") => Ok(Side::North),
+        other => Err(format!("expected side s or n, got {other:?}")),
+    }
+}
+
+fn parse_station(s: Option<&str>) -> Result<String, String> {
+    match s.map(str::trim) {
+        Some(name) if !name.is_empty() => Ok(name.to_string()),
+        _ => Err("expected a station name".to_string()),
+    }
+}
+
+/// A station being calibrated, which may only have one side tagged so far.
+#[derive(Clone, Debug)]
+struct PartialStation {
+    station: String,
+    south: Option<(usize, usize)>,
+    north: Option<(usize, usize)>,
+}
+
+impl PartialStation {
+    fn side_mut(&mut self, side: Side) -> &mut Option<(usize, usize)> {
+        match side {
+            Side::South => &mut self.south,
+            Side::North => &mut self.north,
+        }
+    }
+}
+
+/// Steps a cursor along the strip and collects station tags into a `MapLayout`.
+pub struct Calibrator {
+    total_leds: usize,
+    cursor: usize,
+    segment_len: usize,
+    line_1: Vec<PartialStation>,
+    line_2: Vec<PartialStation>,
+    /// Kept from the layout this started from, since they can't be calibrated here.
+    status_led: Option<usize>,
+    geo: Vec<GeoLed>,
+}
+
+impl Calibrator {
+    pub fn new(total_leds: usize) -> Self {
+        Self {
+            total_leds,
+            cursor: 0,
+            segment_len: 0,
+            line_1: vec![],
+            line_2: vec![],
+            status_led: None,
+            geo: vec![],
+        }
+    }
+
+    /// Starts from an existing layout, so stations can be re-tagged without starting over.
+    pub fn from_layout(layout: &MapLayout) -> Self {
+        let to_partial = |leds: &StationLeds| PartialStation {
+            station: leds.station.clone(),
+            south: Some(leds.south),
+            north: Some(leds.north),
+        };
+        Self {
+            line_1: layout.line(Route::Line1).iter().map(to_partial).collect(),
+            line_2: layout.line(Route::Line2).iter().map(to_partial).collect(),
+            status_led: layout.status_led(),
+            geo: layout.geo().to_vec(),
+            ..Self::new(layout.total_leds())
+        }
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Applies everything except `Write` and `Quit`, which are up to the caller. Returns a message
+    /// for the user.
+    pub fn apply(&mut self, command: Command) -> Result<String, String> {
+        match command {
+            Command::Next => self.move_by(1),
+            Command::Previous => self.move_by(-1),
+            Command::Move(step) => self.move_by(step),
+            Command::GoTo(idx) => {
+                if idx >= self.total_leds {
+                    return Err(format!("LED {idx} is beyond the end of the {} LED strip", self.total_leds));
+                }
+                self.cursor = idx;
+                Ok(self.describe_cursor())
+            },
+            Command::Segment(len) => {
+                self.segment_len = len;
+                Ok(self.describe_cursor())
+            },
+            Command::Tag { route, side, leds_since_prev, station } => {
+                let warning = if stations::contains(route, &station) {
+                    String::new()
+                } else {
+                    format!(" (warning: {station} is not a known {route:?} station)")
+                };
+                let idx = self.cursor;
+                *self.find_or_insert(route, &station).side_mut(side) = Some((idx, leds_since_prev));
+                Ok(format!("tagged LED {idx} as {station} {route:?} {side:?}, {leds_since_prev} LEDs since previous stop{warning}"))
+            },
+            Command::Untag { route, side, station } => {
+                let stations = self.line_mut(route);
+                let Some(partial) = stations.iter_mut().find(|s| s.station == station) else {
+                    return Err(format!("{station} is not tagged on {route:?}"));
+                };
+                *partial.side_mut(side) = None;
+                stations.retain(|s| s.south.is_some() || s.north.is_some());
+                Ok(format!("removed {station} {route:?} {side:?}"))
+            },
+            Command::List => Ok(self.to_string()),
+            Command::Help => Ok(HELP.to_string()),
+            Command::Write | Command::Quit => Ok(String::new()),
+        }
+    }
+
+    /// The frame to show: tagged stations dim, the cursor (and segment) lit.
+    pub fn frame(&self) -> Vec<Led> {
+        let mut leds = vec![LED_OFF; self.total_leds];
+        for partial in self.line_1.iter().chain(self.line_2.iter()) {
+            for (idx, _) in [partial.south, partial.north].into_iter().flatten() {
+                if idx < self.total_leds {
+                    leds[idx] = Led::empty_station();
+                }
+            }
+        }
+        let segment_end = (self.cursor + self.segment_len).min(self.total_leds);
+        if segment_end > self.cursor + 1 {
+            leds[self.cursor + 1..segment_end].fill(SEGMENT_LED);
+        }
+        leds[self.cursor] = CURSOR_LED;
+        leds
+    }
+
+    /// Builds the layout, failing if any tagged station is missing a side.
+    pub fn to_layout(&self) -> Result<MapLayout, String> {
+        let complete = |stations: &[PartialStation]| -> Result<Vec<StationLeds>, String> {
+            stations.iter().map(|partial| match (partial.south, partial.north) {
+                (Some(south), Some(north)) => Ok(StationLeds { station: partial.station.clone(), south, north }),
+                (None, _) => Err(format!("{} has no south LED tagged", partial.station)),
+                (_, None) => Err(format!("{} has no north LED tagged", partial.station)),
+            }).collect()
+        };
+        let layout = MapLayout::new(self.total_leds, complete(&self.line_1)?, complete(&self.line_2)?)
+            .with_geo(self.geo.clone());
+        Ok(match self.status_led {
+            Some(status_led) => layout.with_status_led(status_led),
+            None => layout,
+        })
+    }
+
+    fn move_by(&mut self, step: isize) -> Result<String, String> {
+        let Some(idx) = self.cursor.checked_add_signed(step).filter(|&idx| idx < self.total_leds) else {
+            return Err(format!("can't move {step} from LED {}", self.cursor));
+        };
+        self.cursor = idx;
+        Ok(self.describe_cursor())
+    }
+
+    fn describe_cursor(&self) -> String {
+        let tags: Vec<String> = [(Route::Line1, &self.line_1), (Route::Line2, &self.line_2)].into_iter()
+            .flat_map(|(route, stations)| stations.iter().flat_map(move |partial| {
+                [(Side::South, partial.south), (Side::North, partial.north)].into_iter()
+                    .filter(|(_, leds)| leds.is_some_and(|(idx, _)| idx == self.cursor))
+                    .map(move |(side, _)| format!("{} {route:?} {side:?}", partial.station))
+            }))
+            .collect();
+
+        if tags.is_empty() {
+            format!("LED {}", self.cursor)
+        } else {
+            format!("LED {} [{}]", self.cursor, tags.join(", "))
+        }
+    }
+
+    fn line_mut(&mut self, route: Route) -> &mut Vec<PartialStation> {
+        match route {
+            Route::Line1 => &mut self.line_1,
+            Route::Line2 => &mut self.line_2,
+        }
+    }
+
+    fn find_or_insert(&mut self, route: Route, station: &str) -> &mut PartialStation {
+        let stations = self.line_mut(route);
+        let pos = match stations.iter().position(|s| s.station == station) {
+            Some(pos) => pos,
+            None => {
+                stations.push(PartialStation { station: station.to_string(), south: None, north: None });
+                stations.len() - 1
+            },
+        };
+        &mut stations[pos]
+    }
+}
+
+impl fmt::Display for Calibrator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (route, stations) in [(Route::Line1, &self.line_1), (Route::Line2, &self.line_2)] {
+            writeln!(f, "{route:?}:")?;
+            for partial in stations {
+                writeln!(f, "  {:24} south {:?} north {:?}", partial.station, partial.south, partial.north)?;
+            }
+        }
+        write!(f, "cursor at LED {} of {}", self.cursor, self.total_leds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_and_build_layout() {
+        let mut calibrator = Calibrator::new(10);
+        calibrator.apply(Command::parse("+2").unwrap()).unwrap();
+        calibrator.apply(Command::parse("t 1 s 1 Star Lake").unwrap()).unwrap();
+        assert!(calibrator.to_layout().is_err());
+
+        calibrator.apply(Command::parse("g 7").unwrap()).unwrap();
+        calibrator.apply(Command::parse("t 1 n 2 Star Lake").unwrap()).unwrap();
+        assert!(calibrator.apply(Command::parse("+3").unwrap()).is_err());
+
+        let layout = calibrator.to_layout().unwrap();
+        let star_lake = layout.station(Route::Line1, "Star Lake").unwrap();
+        assert_eq!((star_lake.south, star_lake.north), ((2, 1), (7, 2)));
+        assert!(calibrator.frame()[7] == CURSOR_LED);
+        assert!(calibrator.frame()[2] == Led::empty_station());
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let toml = format!("status_led = 0\ngeo = [{{ led = 1, lat = 47.2871, lon = -122.3131 }}]\n{}", crate::layout::DEFAULT_MAP_LAYOUT);
+        let layout = MapLayout::from_toml_str(&toml).unwrap();
+        assert_eq!(layout.status_led(), Some(0));
+        assert_eq!(Calibrator::from_layout(&layout).to_layout().unwrap().to_toml_string(), layout.to_toml_string());
+    }
+}
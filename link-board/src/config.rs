@@ -0,0 +1,547 @@
+use std::{fs, io::ErrorKind, path::{Path, PathBuf}, time::Duration};
+
+use log::info;
+use serde::Deserialize;
+
+use crate::{display::{DisplayType, Route}, error::{ConfigErr, Error}, local_time::HoursRange, theme::Theme};
+
+/// Env var pointing at the config file, otherwise `DEFAULT_CONFIG_PATH` is used if present.
+pub const CONFIG_PATH_VAR: &str = "LINK_BOARD_CONFIG";
+pub const API_KEY_VAR: &str = "ONEBUSAWAY_API_KEY";
+pub const STATIONS_ONLY_VAR: &str = "STATIONS_ONLY";
+pub const DISPLAY_TYPE_VAR: &str = "LINK_BOARD_DISPLAY_TYPE";
+pub const MAP_LAYOUT_VAR: &str = "LINK_BOARD_MAP_LAYOUT";
+pub const OBA_BASE_URL_VAR: &str = "ONEBUSAWAY_BASE_URL";
+/// Comma separated line numbers, e.g. `1,2`.
+pub const ROUTES_VAR: &str = "LINK_BOARD_ROUTES";
+pub const LINE_1_ROUTE_ID_VAR: &str = "LINK_BOARD_LINE_1_ROUTE_ID";
+pub const LINE_2_ROUTE_ID_VAR: &str = "LINK_BOARD_LINE_2_ROUTE_ID";
+/// Comma separated GTFS-Realtime feed URLs or file paths.
+pub const GTFS_RT_FEEDS_VAR: &str = "LINK_BOARD_GTFS_RT_FEEDS";
+pub const RECORD_DIR_VAR: &str = "LINK_BOARD_RECORD_DIR";
+pub const REQUEST_TIMEOUT_MS_VAR: &str = "LINK_BOARD_REQUEST_TIMEOUT_MS";
+pub const MAX_RETRIES_VAR: &str = "LINK_BOARD_MAX_RETRIES";
+pub const RETRY_DELAY_MS_VAR: &str = "LINK_BOARD_RETRY_DELAY_MS";
+pub const GRACE_PERIOD_SECS_VAR: &str = "LINK_BOARD_GRACE_PERIOD_SECS";
+pub const NO_DATA_AFTER_SECS_VAR: &str = "LINK_BOARD_NO_DATA_AFTER_SECS";
+pub const MAX_POSITION_AGE_SECS_VAR: &str = "LINK_BOARD_MAX_POSITION_AGE_SECS";
+pub const DROP_OLD_POSITIONS_VAR: &str = "LINK_BOARD_DROP_OLD_POSITIONS";
+pub const INCLUDE_SCHEDULE_VAR: &str = "LINK_BOARD_INCLUDE_SCHEDULE";
+pub const GEO_PLACEMENT_VAR: &str = "LINK_BOARD_GEO_PLACEMENT";
+pub const MERGE_LINE_2_VAR: &str = "LINK_BOARD_MERGE_LINE_2";
+pub const BRIGHTNESS_VAR: &str = "LINK_BOARD_BRIGHTNESS";
+pub const GAMMA_VAR: &str = "LINK_BOARD_GAMMA";
+/// Local time range, e.g. `22:00-06:00`.
+pub const NIGHT_HOURS_VAR: &str = "LINK_BOARD_NIGHT_HOURS";
+pub const NIGHT_BRIGHTNESS_VAR: &str = "LINK_BOARD_NIGHT_BRIGHTNESS";
+/// Local time range, e.g. `01:00-05:00`.
+pub const QUIET_HOURS_VAR: &str = "LINK_BOARD_QUIET_HOURS";
+pub const UTC_OFFSET_HOURS_VAR: &str = "LINK_BOARD_UTC_OFFSET_HOURS";
+pub const DAYLIGHT_SAVING_VAR: &str = "LINK_BOARD_DAYLIGHT_SAVING";
+/// A built-in theme name or a theme file, see `theme::Theme::load`.
+pub const THEME_VAR: &str = "LINK_BOARD_THEME";
+
+const DEFAULT_CONFIG_PATH: &str = "link-board.toml";
+const DEFAULT_OBA_BASE_URL: &str = "https://api.pugetsound.onebusaway.org";
+const DEFAULT_LINE_1_ROUTE_ID: &str = "40_100479";
+const DEFAULT_LINE_2_ROUTE_ID: &str = "40_2LINE";
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_RETRY_DELAY_MS: u64 = 500;
+const DEFAULT_GRACE_PERIOD_SECS: u64 = 120;
+const DEFAULT_NO_DATA_AFTER_SECS: u64 = 600;
+const DEFAULT_MAX_POSITION_AGE_SECS: u64 = 180;
+/// With the default gamma, this writes the LED colors as they were tuned before they were full
+/// scale.
+pub(crate) const DEFAULT_BRIGHTNESS: f32 = 0.875;
+pub(crate) const DEFAULT_GAMMA: f32 = 2.2;
+const DEFAULT_NIGHT_BRIGHTNESS: f32 = 0.3;
+const DEFAULT_UTC_OFFSET_HOURS: i8 = -8;
+
+/// Runtime configuration, read from a TOML file with env var overrides.
+#[derive(Clone, Debug)]
+pub struct Config {
+    api_key: String,
+    stations_only: bool,
+    display_type: DisplayType,
+    map_layout: Option<PathBuf>,
+    oba_base_url: String,
+    routes: Vec<Route>,
+    line_1_route_id: String,
+    line_2_route_id: String,
+    gtfs_rt_feeds: Vec<String>,
+    record_dir: Option<PathBuf>,
+    request_timeout_ms: u64,
+    max_retries: u32,
+    retry_delay_ms: u64,
+    grace_period_secs: u64,
+    no_data_after_secs: u64,
+    max_position_age_secs: u64,
+    drop_old_positions: bool,
+    include_schedule: bool,
+    geo_placement: bool,
+    merge_line_2: bool,
+    brightness: f32,
+    gamma: f32,
+    night_hours: Option<HoursRange>,
+    night_brightness: f32,
+    quiet_hours: Option<HoursRange>,
+    utc_offset_hours: i8,
+    daylight_saving: bool,
+    theme: Theme,
+}
+
+/// Config as it appears in the TOML file; every value is optional so env vars can fill the gaps.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    api_key: Option<String>,
+    stations_only: Option<bool>,
+    display_type: Option<u8>,
+    map_layout: Option<PathBuf>,
+    oba_base_url: Option<String>,
+    routes: Option<Vec<u8>>,
+    line_1_route_id: Option<String>,
+    line_2_route_id: Option<String>,
+    gtfs_rt_feeds: Option<Vec<String>>,
+    record_dir: Option<PathBuf>,
+    request_timeout_ms: Option<u64>,
+    max_retries: Option<u32>,
+    retry_delay_ms: Option<u64>,
+    grace_period_secs: Option<u64>,
+    no_data_after_secs: Option<u64>,
+    max_position_age_secs: Option<u64>,
+    drop_old_positions: Option<bool>,
+    include_schedule: Option<bool>,
+    geo_placement: Option<bool>,
+    merge_line_2: Option<bool>,
+    brightness: Option<f32>,
+    gamma: Option<f32>,
+    night_hours: Option<String>,
+    night_brightness: Option<f32>,
+    quiet_hours: Option<String>,
+    utc_offset_hours: Option<i8>,
+    daylight_saving: Option<bool>,
+    theme: Option<String>,
+}
+
+impl Config {
+    /// Reads the config file named by `LINK_BOARD_CONFIG` (or `link-board.toml` if it exists)
+    /// and applies any overrides from the process environment.
+    pub fn load() -> Result<Self, Error> {
+        let (path, required) = match std::env::var(CONFIG_PATH_VAR) {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+
+        let toml = match fs::read_to_string(&path) {
+            Ok(toml) => {
+                info!("loaded config from {}", path.display());
+                Some(toml)
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound && !required => None,
+            Err(e) => return Err(Error::io_error(e)),
+        };
+
+        Self::from_sources(toml.as_deref(), |key| std::env::var(key).ok())
+    }
+
+    /// Builds a config from optional TOML text, with `env` looked up for overrides.
+    pub fn from_sources(toml: Option<&str>, env: impl Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        let mut raw: RawConfig = match toml {
+            Some(toml) => toml::from_str(toml).map_err(|e| Error::config_error(ConfigErr::Toml(e)))?,
+            None => RawConfig::default(),
+        };
+
+        if let Some(api_key) = env(API_KEY_VAR) {
+            raw.api_key = Some(api_key);
+        }
+        if let Some(stations_only) = env(STATIONS_ONLY_VAR) {
+            raw.stations_only = Some(parse_env(STATIONS_ONLY_VAR, &stations_only)?);
+        }
+        if let Some(display_type) = env(DISPLAY_TYPE_VAR) {
+            raw.display_type = Some(parse_env(DISPLAY_TYPE_VAR, &display_type)?);
+        }
+        if let Some(map_layout) = env(MAP_LAYOUT_VAR) {
+            raw.map_layout = Some(PathBuf::from(map_layout));
+        }
+        if let Some(oba_base_url) = env(OBA_BASE_URL_VAR) {
+            raw.oba_base_url = Some(oba_base_url);
+        }
+        if let Some(routes) = env(ROUTES_VAR) {
+            raw.routes = Some(routes.split(',').map(|route| parse_env(ROUTES_VAR, route)).collect::<Result<_, _>>()?);
+        }
+        if let Some(route_id) = env(LINE_1_ROUTE_ID_VAR) {
+            raw.line_1_route_id = Some(route_id);
+        }
+        if let Some(route_id) = env(LINE_2_ROUTE_ID_VAR) {
+            raw.line_2_route_id = Some(route_id);
+        }
+        if let Some(feeds) = env(GTFS_RT_FEEDS_VAR) {
+            raw.gtfs_rt_feeds = Some(feeds.split(',').map(|feed| feed.trim().to_string()).filter(|feed| !feed.is_empty()).collect());
+        }
+        if let Some(record_dir) = env(RECORD_DIR_VAR) {
+            raw.record_dir = Some(PathBuf::from(record_dir));
+        }
+        if let Some(timeout) = env(REQUEST_TIMEOUT_MS_VAR) {
+            raw.request_timeout_ms = Some(parse_env(REQUEST_TIMEOUT_MS_VAR, &timeout)?);
+        }
+        if let Some(max_retries) = env(MAX_RETRIES_VAR) {
+            raw.max_retries = Some(parse_env(MAX_RETRIES_VAR, &max_retries)?);
+        }
+        if let Some(delay) = env(RETRY_DELAY_MS_VAR) {
+            raw.retry_delay_ms = Some(parse_env(RETRY_DELAY_MS_VAR, &delay)?);
+        }
+        if let Some(grace_period) = env(GRACE_PERIOD_SECS_VAR) {
+            raw.grace_period_secs = Some(parse_env(GRACE_PERIOD_SECS_VAR, &grace_period)?);
+        }
+        if let Some(no_data_after) = env(NO_DATA_AFTER_SECS_VAR) {
+            raw.no_data_after_secs = Some(parse_env(NO_DATA_AFTER_SECS_VAR, &no_data_after)?);
+        }
+        if let Some(max_age) = env(MAX_POSITION_AGE_SECS_VAR) {
+            raw.max_position_age_secs = Some(parse_env(MAX_POSITION_AGE_SECS_VAR, &max_age)?);
+        }
+        if let Some(drop_old) = env(DROP_OLD_POSITIONS_VAR) {
+            raw.drop_old_positions = Some(parse_env(DROP_OLD_POSITIONS_VAR, &drop_old)?);
+        }
+        if let Some(include_schedule) = env(INCLUDE_SCHEDULE_VAR) {
+            raw.include_schedule = Some(parse_env(INCLUDE_SCHEDULE_VAR, &include_schedule)?);
+        }
+        if let Some(geo_placement) = env(GEO_PLACEMENT_VAR) {
+            raw.geo_placement = Some(parse_env(GEO_PLACEMENT_VAR, &geo_placement)?);
+        }
+        if let Some(merge_line_2) = env(MERGE_LINE_2_VAR) {
+            raw.merge_line_2 = Some(parse_env(MERGE_LINE_2_VAR, &merge_line_2)?);
+        }
+        if let Some(brightness) = env(BRIGHTNESS_VAR) {
+            raw.brightness = Some(parse_env(BRIGHTNESS_VAR, &brightness)?);
+        }
+        if let Some(gamma) = env(GAMMA_VAR) {
+            raw.gamma = Some(parse_env(GAMMA_VAR, &gamma)?);
+        }
+        if let Some(night_hours) = env(NIGHT_HOURS_VAR) {
+            raw.night_hours = Some(night_hours);
+        }
+        if let Some(night_brightness) = env(NIGHT_BRIGHTNESS_VAR) {
+            raw.night_brightness = Some(parse_env(NIGHT_BRIGHTNESS_VAR, &night_brightness)?);
+        }
+        if let Some(quiet_hours) = env(QUIET_HOURS_VAR) {
+            raw.quiet_hours = Some(quiet_hours);
+        }
+        if let Some(utc_offset) = env(UTC_OFFSET_HOURS_VAR) {
+            raw.utc_offset_hours = Some(parse_env(UTC_OFFSET_HOURS_VAR, &utc_offset)?);
+        }
+        if let Some(daylight_saving) = env(DAYLIGHT_SAVING_VAR) {
+            raw.daylight_saving = Some(parse_env(DAYLIGHT_SAVING_VAR, &daylight_saving)?);
+        }
+        if let Some(theme) = env(THEME_VAR) {
+            raw.theme = Some(theme);
+        }
+
+        let gtfs_rt_feeds = raw.gtfs_rt_feeds.unwrap_or_default();
+        // OneBusAway isn't used when reading GTFS-Realtime feeds
+        let api_key = match raw.api_key {
+            Some(api_key) if !api_key.is_empty() => api_key,
+            _ if !gtfs_rt_feeds.is_empty() => String::new(),
+            _ => return Err(Error::config_error(ConfigErr::Missing("api_key"))),
+        };
+        let display_type = match raw.display_type {
+            Some(display_type) => DisplayType::try_from(display_type)
+                .map_err(|_| Error::config_error(ConfigErr::Invalid { key: "display_type", value: display_type.to_string() }))?,
+            None => DisplayType::default(),
+        };
+        let routes = match raw.routes {
+            Some(routes) => parse_routes(&routes)?,
+            None => vec![Route::Line1, Route::Line2],
+        };
+
+        let grace_period_secs = raw.grace_period_secs.unwrap_or(DEFAULT_GRACE_PERIOD_SECS);
+        let no_data_after_secs = raw.no_data_after_secs.unwrap_or(DEFAULT_NO_DATA_AFTER_SECS);
+        if no_data_after_secs < grace_period_secs {
+            return Err(Error::config_error(ConfigErr::Invalid { key: "no_data_after_secs", value: no_data_after_secs.to_string() }));
+        }
+
+        let brightness = parse_fraction("brightness", raw.brightness, DEFAULT_BRIGHTNESS)?;
+        let night_brightness = parse_fraction("night_brightness", raw.night_brightness, DEFAULT_NIGHT_BRIGHTNESS)?;
+        let gamma = raw.gamma.unwrap_or(DEFAULT_GAMMA);
+        if !(gamma > 0.0 && gamma.is_finite()) {
+            return Err(Error::config_error(ConfigErr::Invalid { key: "gamma", value: gamma.to_string() }));
+        }
+        let utc_offset_hours = raw.utc_offset_hours.unwrap_or(DEFAULT_UTC_OFFSET_HOURS);
+        if !(-12..=14).contains(&utc_offset_hours) {
+            return Err(Error::config_error(ConfigErr::Invalid { key: "utc_offset_hours", value: utc_offset_hours.to_string() }));
+        }
+
+        let theme = match raw.theme {
+            Some(theme) => Theme::load(&theme)?,
+            None => Theme::default(),
+        };
+
+        Ok(Self {
+            api_key,
+            stations_only: raw.stations_only.unwrap_or(false),
+            display_type,
+            map_layout: raw.map_layout,
+            oba_base_url: raw.oba_base_url.unwrap_or_else(|| DEFAULT_OBA_BASE_URL.to_string()),
+            routes,
+            line_1_route_id: raw.line_1_route_id.unwrap_or_else(|| DEFAULT_LINE_1_ROUTE_ID.to_string()),
+            line_2_route_id: raw.line_2_route_id.unwrap_or_else(|| DEFAULT_LINE_2_ROUTE_ID.to_string()),
+            gtfs_rt_feeds,
+            record_dir: raw.record_dir,
+            request_timeout_ms: match raw.request_timeout_ms {
+                Some(0) => return Err(Error::config_error(ConfigErr::Invalid { key: "request_timeout_ms", value: "0".to_string() })),
+                timeout => timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT_MS),
+            },
+            max_retries: raw.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+            retry_delay_ms: raw.retry_delay_ms.unwrap_or(DEFAULT_RETRY_DELAY_MS),
+            grace_period_secs,
+            no_data_after_secs,
+            max_position_age_secs: raw.max_position_age_secs.unwrap_or(DEFAULT_MAX_POSITION_AGE_SECS),
+            drop_old_positions: raw.drop_old_positions.unwrap_or(false),
+            include_schedule: raw.include_schedule.unwrap_or(true),
+            geo_placement: raw.geo_placement.unwrap_or(false),
+            merge_line_2: raw.merge_line_2.unwrap_or(false),
+            brightness,
+            gamma,
+            night_hours: parse_hours("night_hours", raw.night_hours)?,
+            night_brightness,
+            quiet_hours: parse_hours("quiet_hours", raw.quiet_hours)?,
+            utc_offset_hours,
+            daylight_saving: raw.daylight_saving.unwrap_or(true),
+            theme,
+        })
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    pub fn stations_only(&self) -> bool {
+        self.stations_only
+    }
+
+    pub fn display_type(&self) -> DisplayType {
+        self.display_type
+    }
+
+    /// Layout file for the map display; the bundled layout is used if this is `None`.
+    pub fn map_layout(&self) -> Option<&Path> {
+        self.map_layout.as_deref()
+    }
+
+    /// Root of the OneBusAway API, without a trailing slash.
+    pub fn oba_base_url(&self) -> &str {
+        self.oba_base_url.trim_end_matches('/')
+    }
+
+    /// Routes to fetch and display, in the order they are fetched.
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    /// GTFS-Realtime feeds to read trains from instead of OneBusAway, if any.
+    pub fn gtfs_rt_feeds(&self) -> &[String] {
+        &self.gtfs_rt_feeds
+    }
+
+    /// Directory to record OneBusAway responses in, see `data_retriever::recording`.
+    pub fn record_dir(&self) -> Option<&Path> {
+        self.record_dir.as_deref()
+    }
+
+    /// Timeout for each request to OneBusAway or a GTFS-Realtime feed.
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout_ms)
+    }
+
+    /// Retries after a failed request, see `retry::RetryPolicy`.
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Wait before the first retry.
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_millis(self.retry_delay_ms)
+    }
+
+    /// How long the last trains fetched are shown as-is after fetches start failing.
+    pub fn grace_period(&self) -> Duration {
+        Duration::from_secs(self.grace_period_secs)
+    }
+
+    /// How long after the last successful fetch the board stops showing trains altogether.
+    pub fn no_data_after(&self) -> Duration {
+        Duration::from_secs(self.no_data_after_secs)
+    }
+
+    /// Age at which a train's reported position is considered old, see `Train::is_old`.
+    pub fn max_position_age(&self) -> Duration {
+        Duration::from_secs(self.max_position_age_secs)
+    }
+
+    /// Whether trains with old positions are left off the board instead of dimmed.
+    pub fn drop_old_positions(&self) -> bool {
+        self.drop_old_positions
+    }
+
+    /// Whether OneBusAway is asked for each trip's schedule, whose stop distances place trains
+    /// between stations. It makes responses several times larger.
+    pub fn include_schedule(&self) -> bool {
+        self.include_schedule
+    }
+
+    /// Whether the map display places trains on the LED nearest their reported position, for
+    /// layouts with coordinates, instead of by their next stop.
+    pub fn geo_placement(&self) -> bool {
+        self.geo_placement
+    }
+
+    /// Whether the strip and string displays draw 2 Line trains on the 1 Line's LEDs from CID to
+    /// Lynnwood, leaving only the Eastside part of the 2 Line in its own section.
+    pub fn merge_line_2(&self) -> bool {
+        self.merge_line_2
+    }
+
+    /// Scale for every LED, from 0 to 1, applied after gamma correction.
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    /// Exponent taking the full scale LED colors to what's written to the LEDs.
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    /// Local hours during which `night_brightness` is applied on top of `brightness`.
+    pub fn night_hours(&self) -> Option<HoursRange> {
+        self.night_hours
+    }
+
+    /// Scale for every LED during `night_hours`, from 0 to 1.
+    pub fn night_brightness(&self) -> f32 {
+        self.night_brightness
+    }
+
+    /// Local hours during which the board is off. These win over `night_hours`.
+    pub fn quiet_hours(&self) -> Option<HoursRange> {
+        self.quiet_hours
+    }
+
+    /// Offset of local standard time from UTC, for `night_hours` and `quiet_hours`.
+    pub fn utc_offset_hours(&self) -> i8 {
+        self.utc_offset_hours
+    }
+
+    /// Whether US daylight saving time moves local time an hour ahead in the summer.
+    pub fn daylight_saving(&self) -> bool {
+        self.daylight_saving
+    }
+
+    /// Colors every display draws with.
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// OneBusAway route ID for `route`; GTFS route IDs are matched without the agency prefix.
+    pub fn route_id(&self, route: Route) -> &str {
+        match route {
+            Route::Line1 => &self.line_1_route_id,
+            Route::Line2 => &self.line_2_route_id,
+        }
+    }
+}
+
+fn parse_routes(routes: &[u8]) -> Result<Vec<Route>, Error> {
+    let invalid = || Error::config_error(ConfigErr::Invalid { key: "routes", value: format!("{routes:?}") });
+    let mut parsed: Vec<Route> = vec![];
+    for &route in routes {
+        let route = Route::try_from(route).map_err(|_| invalid())?;
+        if !parsed.contains(&route) {
+            parsed.push(route);
+        }
+    }
+    if parsed.is_empty() {
+        return Err(invalid());
+    }
+    Ok(parsed)
+}
+
+/// `value`, or `default` if it isn't set, which must be from 0 to 1.
+fn parse_fraction(key: &'static str, value: Option<f32>, default: f32) -> Result<f32, Error> {
+    match value {
+        Some(value) if !(0.0..=1.0).contains(&value) => Err(Error::config_error(ConfigErr::Invalid { key, value: value.to_string() })),
+        value => Ok(value.unwrap_or(default)),
+    }
+}
+
+fn parse_hours(key: &'static str, value: Option<String>) -> Result<Option<HoursRange>, Error> {
+    value.map(|value| value.parse().map_err(|_| Error::config_error(ConfigErr::Invalid { key, value }))).transpose()
+}
+
+fn parse_env<T: std::str::FromStr>(key: &'static str, value: &str) -> Result<T, Error> {
+    value.trim().parse().map_err(|_| Error::config_error(ConfigErr::Invalid { key, value: value.to_string() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_overrides_file() {
+        let toml = "api_key = \"from-file\"\nstations_only = true\ndisplay_type = 1\n";
+        let config = Config::from_sources(Some(toml), |key| match key {
+            DISPLAY_TYPE_VAR => Some("2".to_string()),
+            _ => None,
+        }).unwrap();
+
+        assert_eq!(config.api_key(), "from-file");
+        assert!(config.stations_only());
+        assert_eq!(config.display_type(), DisplayType::MapDisplay);
+        assert_eq!(config.routes(), [Route::Line1, Route::Line2]);
+    }
+
+    #[test]
+    fn test_routes_and_endpoint() {
+        let toml = "api_key = \"key\"\noba_base_url = \"http://localhost:8080/\"\nline_2_route_id = \"1_2LINE\"\n";
+        let config = Config::from_sources(Some(toml), |key| match key {
+            ROUTES_VAR => Some("2".to_string()),
+            _ => None,
+        }).unwrap();
+
+        assert_eq!(config.oba_base_url(), "http://localhost:8080");
+        assert_eq!(config.routes(), [Route::Line2]);
+        assert_eq!(config.route_id(Route::Line1), DEFAULT_LINE_1_ROUTE_ID);
+        assert_eq!(config.route_id(Route::Line2), "1_2LINE");
+        assert!(Config::from_sources(Some("api_key = \"key\"\nroutes = []\n"), |_| None).is_err());
+    }
+
+    #[test]
+    fn test_missing_and_invalid_values_are_errors() {
+        assert!(Config::from_sources(None, |_| None).is_err());
+        assert!(Config::from_sources(Some("api_key = \"key\"\ndisplay_type = 7\n"), |_| None).is_err());
+        assert!(Config::from_sources(Some("api_key = \"key\"\n"), |key| match key {
+            STATIONS_ONLY_VAR => Some("yes".to_string()),
+            _ => None,
+        }).is_err());
+        assert!(Config::from_sources(Some("api_key = \"key\"\nbrightness = 1.5\n"), |_| None).is_err());
+        assert!(Config::from_sources(Some("api_key = \"key\"\ngamma = 0.0\n"), |_| None).is_err());
+        assert!(Config::from_sources(Some("api_key = \"key\"\nquiet_hours = \"1am-5am\"\n"), |_| None).is_err());
+        assert!(Config::from_sources(Some("api_key = \"key\"\ntheme = \"no-such-theme.toml\"\n"), |_| None).is_err());
+    }
+
+    #[test]
+    fn test_night_and_quiet_hours() {
+        let toml = "api_key = \"key\"\nnight_hours = \"22:00-06:00\"\n";
+        let config = Config::from_sources(Some(toml), |key| match key {
+            QUIET_HOURS_VAR => Some("01:00-05:00".to_string()),
+            UTC_OFFSET_HOURS_VAR => Some("-5".to_string()),
+            _ => None,
+        }).unwrap();
+
+        assert_eq!(config.night_hours().unwrap().to_string(), "22:00-06:00");
+        assert_eq!(config.quiet_hours().unwrap().to_string(), "01:00-05:00");
+        assert_eq!(config.night_brightness(), DEFAULT_NIGHT_BRIGHTNESS);
+        assert_eq!(config.utc_offset_hours(), -5);
+        assert!(config.daylight_saving());
+    }
+}
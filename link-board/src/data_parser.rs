@@ -1,26 +1,44 @@
-use crate::{constants::Destination, data_retriever::DataRetriever, display::Route, error::Error, train::Train, trips_for_route_types::TripsForRoute};
-use std::collections::HashMap;
+use crate::{
+    constants::Destination,
+    data_retriever::DataRetriever,
+    display::Route,
+    error::{ApiErr, Error, TripParseErr},
+    stations,
+    train::{Train, TrainIds},
+    trips_for_route_types::{Data, LatLon, TripDetails, TripSchedule, TripStatus, TripsForRoute}
+};
+use std::{collections::HashMap, time::Duration};
 use log::{info, warn};
 
 pub async fn get_all_trains(data_retriever: &impl DataRetriever) -> Result<Vec<Train>, Error> {
     let mut all_trains = vec![];
+    let mut all_skipped = 0;
     let trains_json = data_retriever.get_json_for_all_trains().await?;
 
     for (route, json) in trains_json {
-        let mut trains = parse_route(&json, route)?;
+        let (mut trains, skipped) = parse_route(&json, route)?;
         all_trains.append(&mut trains);
+        all_skipped += skipped;
     }
 
+    if all_skipped > 0 {
+        warn!("skipped {} of {} trains", all_skipped, all_trains.len() + all_skipped);
+    }
     Ok(all_trains)
 }
 
-fn parse_route(json_string: &String, route: Route) -> Result<Vec<Train>, Error> {
+/// Returns the trains on `route` and the number of trips that were skipped because they couldn't
+/// be placed on the board.
+fn parse_route(json_string: &str, route: Route) -> Result<(Vec<Train>, usize), Error> {
     let mut trains = vec![];
+    let mut skipped = 0;
     let trips_for_route: TripsForRoute = serde_json::from_str(json_string)?;
+    let current_time = trips_for_route.current_time;
+    let data = check_envelope(trips_for_route, json_string)?;
     info!("successfully parsed trips for route");
 
     let mut trip_ids_to_dests = HashMap::new();
-    for trip in trips_for_route.data.references.trips {
+    for trip in data.references.trips {
         if let Some(direction_id) = dir_id_to_destination(trip.direction_id.as_deref(), route) {
             trip_ids_to_dests.insert(trip.id,direction_id);
         } else {
@@ -29,45 +47,139 @@ fn parse_route(json_string: &String, route: Route) -> Result<Vec<Train>, Error>
     }
 
     let mut stops_to_names = HashMap::new();
-    for stop in trips_for_route.data.references.stops {
-        stops_to_names.insert(stop.id, stop.name);
-    }
-    
-    for trip in trips_for_route.data.list {
-        let Some(status) = trip.status else {
-            warn!("status missing for {}", trip.trip_id);
-            continue;
-        };
-        let Some(next_stop) = status.next_stop else {
-            warn!("no next stop for {}", trip.trip_id);
-            continue;
-        };
-        let Some(next_stop_time_offset) = status.next_stop_time_offset else {
-            warn!("no next stop time offset for {}", trip.trip_id);
-            continue;
+    for stop in data.references.stops {
+        match stations::for_stop(&stop.id, stop.parent.as_deref(), &stop.name) {
+            Some(station) if stations::contains(route, station.name) => {
+                stops_to_names.insert(stop.id, station.name.to_string());
+            },
+            _ => warn!("stop {} ({}) is not a {:?} station", stop.id, stop.name, route),
+        }
+    }
+
+    for trip in data.list {
+        match parse_trip(&trip, route, &trip_ids_to_dests, &stops_to_names, current_time) {
+            Ok(train) => trains.push(train),
+            Err(e) => {
+                warn!("skipping trip {} on route {:?}: {e}", trip.trip_id, route);
+                skipped += 1;
+            },
+        }
+    }
+
+    Ok((trains, skipped))
+}
+
+/// Returns the response's data, or an error for the status OneBusAway reported.
+fn check_envelope(trips_for_route: TripsForRoute, json_string: &str) -> Result<Data, Error> {
+    match trips_for_route.code {
+        None | Some(200) => {},
+        Some(429) => return Err(Error::rate_limited_error(json_string, None)),
+        Some(code) => return Err(Error::api_error(ApiErr::Code { code, text: trips_for_route.text.unwrap_or_default() })),
+    }
+    match trips_for_route.data {
+        Some(data) if data.limit_exceeded => Err(Error::api_error(ApiErr::LimitExceeded)),
+        Some(data) => Ok(data),
+        None => Err(Error::api_error(ApiErr::MissingData)),
+    }
+}
+
+fn parse_trip(
+    trip: &TripDetails,
+    route: Route,
+    trip_ids_to_dests: &HashMap<String, Destination>,
+    stops_to_names: &HashMap<String, String>,
+    current_time: Option<i64>
+) -> Result<Train, Error> {
+    let Some(status) = &trip.status else {
+        return Err(Error::trip_parse_error(TripParseErr::Status));
+    };
+    let ids = TrainIds {
+        trip_id: trip.trip_id.clone(),
+        vehicle_id: status.vehicle_id.clone(),
+        active_trip_id: status.active_trip_id.clone(),
+        block_trip_sequence: status.block_trip_sequence,
+    };
+    let position = vehicle_position(status);
+    let Some(next_stop) = &status.next_stop else {
+        // without a next stop the train can still be placed by where it is
+        let (Some(position), Some(&destination)) = (position, trip_ids_to_dests.get(&trip.trip_id)) else {
+            return Err(Error::trip_parse_error(TripParseErr::NextStop));
         };
-        
-        if let Some(sched_dist) = status.scheduled_distance_along_trip {
-            if sched_dist == 0.0 {
-                warn!("trip {} not in progress yet on route {:?}, scheduledDistanceAlongTrip: {}", trip.trip_id, route, sched_dist);
-            }
-        } else {
-            warn!("trip {} not in progress yet on route {:?}, no scheduledDistanceAlongTrip", trip.trip_id, route);
+        let offset = status.next_stop_time_offset.unwrap_or(1).max(1);
+        return Ok(Train::new(String::new(), route, destination, offset, offset.max(status.closest_stop_time_offset))
+            .with_age(position_age(status, current_time))
+            .with_ids(ids)
+            .with_position(Some(position)));
+    };
+    let Some(next_stop_time_offset) = status.next_stop_time_offset else {
+        return Err(Error::trip_parse_error(TripParseErr::NextStopTimeOffset));
+    };
+
+    if let Some(sched_dist) = status.scheduled_distance_along_trip {
+        if sched_dist == 0.0 {
+            warn!("trip {} not in progress yet on route {:?}, scheduledDistanceAlongTrip: {}", trip.trip_id, route, sched_dist);
         }
+    } else {
+        warn!("trip {} not in progress yet on route {:?}, no scheduledDistanceAlongTrip", trip.trip_id, route);
+    }
+
+    let Some(next_stop_name) = stops_to_names.get(next_stop) else {
+        return Err(Error::unknown_stop_error(next_stop.clone()));
+    };
+    let Some(&destination) = trip_ids_to_dests.get(&trip.trip_id) else {
+        return Err(Error::unknown_trip_error(trip.trip_id.clone()));
+    };
+
+    Ok(Train::new(
+        next_stop_name.clone(),
+        route,
+        destination,
+        next_stop_time_offset,
+        status.closest_stop_time_offset
+    ).with_age(position_age(status, current_time))
+        .with_ids(ids)
+        .with_progress(trip.schedule.as_ref().and_then(|schedule| segment_progress(status, next_stop, schedule)))
+        .with_position(position))
+}
+
+/// The vehicle's (latitude, longitude), preferring its estimated position over where it was last
+/// seen. OneBusAway reports 0, 0 for vehicles it hasn't located.
+fn vehicle_position(status: &TripStatus) -> Option<(f64, f64)> {
+    [status.position, status.last_known_location].into_iter()
+        .flatten()
+        .find(|LatLon { lat, lon }| *lat != 0.0 || *lon != 0.0)
+        .map(|LatLon { lat, lon }| (lat, lon))
+}
 
-        trains.push(Train::new(
-            stops_to_names[&next_stop].clone(),
-            route,
-            trip_ids_to_dests[&trip.trip_id],
-            next_stop_time_offset,
-            status.closest_stop_time_offset
-        ));
+/// How far the train is from the stop before `next_stop` to `next_stop`, from 0 to 1, by distance
+/// along the trip. `None` without a schedule, or at the first stop.
+fn segment_progress(status: &TripStatus, next_stop: &str, schedule: &TripSchedule) -> Option<f64> {
+    let distance = status.distance_along_trip.or(status.scheduled_distance_along_trip)?;
+    let next = schedule.stop_times.iter().position(|stop_time| stop_time.stop_id == next_stop)?;
+    let next_distance = schedule.stop_times[next].distance_along_trip;
+    let prev_distance = schedule.stop_times[next.checked_sub(1)?].distance_along_trip;
+    if next_distance <= prev_distance {
+        return None;
     }
+    Some(((distance - prev_distance) / (next_distance - prev_distance)).clamp(0.0, 1.0))
+}
 
-    Ok(trains)
+/// How old the trip's position was at the response's `currentTime`, or `None` if it's only from
+/// the schedule.
+fn position_age(status: &TripStatus, current_time: Option<i64>) -> Option<Duration> {
+    if !status.predicted {
+        return None;
+    }
+    let last_update = [status.last_update_time, status.last_location_update_time]
+        .into_iter()
+        .flatten()
+        .filter(|&time| time > 0)
+        .max()?;
+    let age = current_time?.saturating_sub(last_update).max(0);
+    Some(Duration::from_millis(age as u64))
 }
 
-fn dir_id_to_destination(dir_id: Option<&str>, route: Route) -> Option<Destination> {
+pub(crate) fn dir_id_to_destination(dir_id: Option<&str>, route: Route) -> Option<Destination> {
     // directionId can only be 0 or 1 per GTFS docs
     match dir_id {
         Some("0") => match route {
@@ -80,4 +192,86 @@ fn dir_id_to_destination(dir_id: Option<&str>, route: Route) -> Option<Destinati
             None
         },
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{TEST_LARGE_PAYLOAD_1_LINE, TEST_LARGE_PAYLOAD_2_LINE};
+
+    #[test]
+    fn test_trains_use_registry_names() {
+        for (json, route) in [(TEST_LARGE_PAYLOAD_1_LINE, Route::Line1), (TEST_LARGE_PAYLOAD_2_LINE, Route::Line2)] {
+            let (trains, skipped) = parse_route(json, route).unwrap();
+            assert!(!trains.is_empty());
+            assert_eq!(skipped, 0);
+            assert!(trains.iter().all(|t| stations::contains(route, &t.next_stop_name)));
+            assert!(trains.iter().all(|t| t.tracking_key().is_some_and(|key| !key.contains('['))));
+        }
+    }
+
+    #[test]
+    fn test_unknown_stop_and_trip_are_skipped() {
+        let (trains, _) = parse_route(TEST_LARGE_PAYLOAD_1_LINE, Route::Line1).unwrap();
+        let json = TEST_LARGE_PAYLOAD_1_LINE
+            .replacen(r#""nextStop":"40_99903""#, r#""nextStop":"40_DETOUR""#, 1)
+            .replacen(r#""directionId":"1""#, r#""directionId":"""#, 1);
+        let (remaining, skipped) = parse_route(&json, Route::Line1).unwrap();
+        assert_eq!(skipped, 2);
+        assert_eq!(remaining.len(), trains.len() - 2);
+    }
+
+    #[test]
+    fn test_trains_without_next_stop_keep_position() {
+        let (trains, _) = parse_route(TEST_LARGE_PAYLOAD_1_LINE, Route::Line1).unwrap();
+        assert_eq!(trains[0].position(), Some((47.42795638113982, -122.2977051912403)));
+
+        let json = TEST_LARGE_PAYLOAD_1_LINE.replacen(r#""nextStop":"40_99903","#, "", 1);
+        let (remaining, skipped) = parse_route(&json, Route::Line1).unwrap();
+        assert_eq!((remaining.len(), skipped), (trains.len(), 0));
+        assert_eq!(remaining[0].next_stop_name, "");
+        assert_eq!(remaining[0].position(), trains[0].position());
+    }
+
+    #[test]
+    fn test_api_errors() {
+        let invalid_key = r#"{"code":401,"currentTime":1738287794722,"text":"permission denied","version":1}"#;
+        let err = parse_route(invalid_key, Route::Line1).unwrap_err();
+        assert_eq!(err.to_string(), "OneBusAway error: code 401: permission denied");
+
+        let rate_limited = r#"{"code":429,"text":"too many requests"}"#;
+        assert!(parse_route(rate_limited, Route::Line1).unwrap_err().to_string().starts_with("rate limited: "));
+
+        let truncated = TEST_LARGE_PAYLOAD_1_LINE.replacen(r#""limitExceeded":false"#, r#""limitExceeded":true"#, 1);
+        assert!(parse_route(&truncated, Route::Line1).unwrap_err().to_string().contains("limit exceeded"));
+    }
+
+    #[test]
+    fn test_progress_from_schedule() {
+        let (trains, _) = parse_route(TEST_LARGE_PAYLOAD_1_LINE, Route::Line1).unwrap();
+        assert!(trains.iter().all(|t| t.progress().is_none()));
+
+        // the first trip is 538.6m along, a quarter of the way from Federal Way to Star Lake
+        let schedule = r#""schedule":{"stopTimes":[{"stopId":"40_99913","distanceAlongTrip":0.0},{"stopId":"40_99903","distanceAlongTrip":2154.4815544111108}]},"#;
+        let json = TEST_LARGE_PAYLOAD_1_LINE.replacen(r#""list":[{"#, &format!(r#""list":[{{{schedule}"#), 1);
+        let (trains, _) = parse_route(&json, Route::Line1).unwrap();
+        let progress: Vec<f64> = trains.iter().filter_map(Train::progress).collect();
+        assert_eq!(progress.len(), 1);
+        assert!((progress[0] - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_position_age() {
+        let (trains, _) = parse_route(TEST_LARGE_PAYLOAD_1_LINE, Route::Line1).unwrap();
+        assert!(trains.iter().filter_map(Train::age).all(|age| age < Duration::from_secs(120)));
+        assert!(trains.iter().any(|t| t.age().is_some()));
+
+        // 10 minutes before `currentTime`
+        let json = TEST_LARGE_PAYLOAD_1_LINE
+            .replacen(r#""lastLocationUpdateTime":1738287707000"#, r#""lastLocationUpdateTime":1738287194722"#, 1)
+            .replacen(r#""lastUpdateTime":1738287707000"#, r#""lastUpdateTime":1738287194722"#, 1);
+        let (trains, _) = parse_route(&json, Route::Line1).unwrap();
+        let ages: Vec<Duration> = trains.iter().filter_map(Train::age).collect();
+        assert!(ages.contains(&Duration::from_secs(600)));
+    }
+}
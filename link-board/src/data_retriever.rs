@@ -1,66 +1,95 @@
 #![allow(async_fn_in_trait)]
 
-use crate::{display::Route, error::Error};
+use crate::{config::Config, data_parser, display::Route, error::Error, retry::parse_retry_after, train::Train};
 
-const LINE_1_ROUTE_ID: &str = "40_100479";
-const LINE_2_ROUTE_ID: &str = "40_2LINE";
+#[cfg(feature = "cli")]
+pub mod recording;
+
+/// Anything the board can get the current trains from.
+pub trait TrainSource {
+    async fn get_all_trains(&self) -> Result<Vec<Train>, Error>;
+}
+
+/// OneBusAway JSON is parsed into trains by `data_parser`.
+impl<T: DataRetriever> TrainSource for T {
+    async fn get_all_trains(&self) -> Result<Vec<Train>, Error> {
+        data_parser::get_all_trains(self).await
+    }
+}
 
 pub trait DataRetriever {
     async fn get_json_for_all_trains(&self) -> Result<Vec<(Route, String)>, Error>;
 
-    fn url_for_route(route: Route, api_key: String) -> String {
-        let route_id = match route {
-            Route::Line1 => LINE_1_ROUTE_ID,
-            Route::Line2 => LINE_2_ROUTE_ID,
-        };
+    fn url_for_route(route: Route, config: &Config) -> String {
         format!(
-            "https://api.pugetsound.onebusaway.org/api/where/trips-for-route/{}.json?includeSchedule=false&includeStatus=true&key={}",
-            route_id,
-            api_key
+            "{}/api/where/trips-for-route/{}.json?includeSchedule={}&includeStatus=true&key={}",
+            config.oba_base_url(),
+            config.route_id(route),
+            config.include_schedule(),
+            config.api_key()
         )
     }
 }
 
+/// Returns the body of a successful response, or an error with the status and start of the body.
+/// `retry_after` is the response's `Retry-After` header, if any.
+pub fn check_status(status: u16, retry_after: Option<&str>, body: String) -> Result<String, Error> {
+    match status {
+        200..=299 => Ok(body),
+        429 => Err(Error::rate_limited_error(&body, retry_after.and_then(parse_retry_after))),
+        _ => Err(Error::http_status_error(status, &body)),
+    }
+}
+
 #[cfg(feature = "cli")]
 pub mod dr {
-    use crate::{data_retriever::DataRetriever, display::Route, env, error::Error};
+    use reqwest::header::RETRY_AFTER;
+
+    use crate::{
+        config::Config,
+        data_retriever::{check_status, DataRetriever},
+        display::Route,
+        error::Error,
+        retry::{retry, RetryPolicy}
+    };
 
     const CONCURRENT_REQUESTS: usize = 2;
     
-    pub fn get_data_retriever() -> impl DataRetriever {
-        DataRetrieverImpl::default()
+    pub fn get_data_retriever(config: &Config) -> impl DataRetriever {
+        DataRetrieverImpl::new(config)
     }
     
-    #[derive(Default)]
-    pub struct DataRetrieverImpl;
+    pub struct DataRetrieverImpl {
+        config: Config,
+    }
+
+    impl DataRetrieverImpl {
+        pub fn new(config: &Config) -> Self {
+            Self {
+                config: config.clone(),
+            }
+        }
+    }
     
     impl DataRetriever for DataRetrieverImpl {
         async fn get_json_for_all_trains(&self) -> Result<Vec<(Route, String)>, Error> {
             use futures::{stream, StreamExt};
             use log::debug;
     
-            let routes = vec![Route::Line1, Route::Line2];
-            let urls = routes.into_iter().map(|route| (route, DataRetrieverImpl::url_for_route(route, env::api_key())));
+            let routes = self.config.routes();
+            let urls = routes.iter().map(|&route| (route, DataRetrieverImpl::url_for_route(route, &self.config)));
             let mut results = Vec::with_capacity(urls.len());
-            let client =  reqwest::Client::new();
+            let policy = RetryPolicy::new(&self.config);
+            let client = reqwest::Client::builder().timeout(policy.timeout).build()?;
     
             let fetches = stream::iter(
                 urls.map(|(route, url)| {
                     let mut results = vec![];
                     let client = client.clone();
                     async move {
-                        match client.get(&url).send().await {
-                            Ok(response) => {
-                                match response.text().await {
-                                    Ok(text) => {
-                                        debug!("retrieved text of len {} for route {:?}", text.len(), route);
-                                        results.push((route, text.to_owned()))
-                                    },
-                                    Err(e) => return Err(Error::client_error(e))
-                                }
-                            },
-                            Err(e) => return Err(Error::client_error(e))
-                        }
+                        let text = retry(&policy, || fetch(&client, &url), tokio::time::sleep).await?;
+                        debug!("retrieved text of len {} for route {:?}", text.len(), route);
+                        results.push((route, text));
                         Ok(results)
                     }
                 })
@@ -77,4 +106,11 @@ pub mod dr {
             Ok(results)
         }
     }
-}
\ No newline at end of file
+
+    async fn fetch(client: &reqwest::Client, url: &str) -> Result<String, Error> {
+        let response = client.get(url).send().await?;
+        let status = response.status().as_u16();
+        let retry_after = response.headers().get(RETRY_AFTER).and_then(|v| v.to_str().ok()).map(str::to_string);
+        check_status(status, retry_after.as_deref(), response.text().await?)
+    }
+}
@@ -0,0 +1,215 @@
+//! Recording OneBusAway responses to disk and playing them back, so a placement seen on the wall
+//! can be reproduced later.
+//!
+//! A recording is a directory of `{unix millis}-{line}.json` files, one per route per fetch. Files
+//! with the same timestamp are played back together as one frame.
+
+use std::{
+    fs,
+    future,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+
+use log::{info, warn};
+
+use crate::{data_retriever::DataRetriever, display::Route, error::Error};
+
+/// Saves every response from `inner` into a new session directory under the record directory.
+pub struct RecordingRetriever<D> {
+    inner: D,
+    session: PathBuf,
+}
+
+impl<D: DataRetriever> RecordingRetriever<D> {
+    pub fn new(inner: D, record_dir: &Path) -> Result<Self, Error> {
+        let session = record_dir.join(format!("session-{}", unix_millis() / 1000));
+        fs::create_dir_all(&session)?;
+        info!("recording OneBusAway responses to {}", session.display());
+        Ok(Self { inner, session })
+    }
+}
+
+impl<D: DataRetriever> DataRetriever for RecordingRetriever<D> {
+    async fn get_json_for_all_trains(&self) -> Result<Vec<(Route, String)>, Error> {
+        let results = self.inner.get_json_for_all_trains().await?;
+        let millis = unix_millis();
+        for (route, json) in &results {
+            let path = self.session.join(format!("{millis}-{}.json", line_number(*route)));
+            // a failed recording shouldn't take the board down
+            if let Err(e) = fs::write(&path, json) {
+                warn!("failed to record {}: {e}", path.display());
+            }
+        }
+        Ok(results)
+    }
+}
+
+/// How a recording is played back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Playback {
+    /// Frames are spaced out as they were recorded, divided by the factor (1.0 for real speed).
+    Speed(f64),
+    /// Frames are played as soon as they're fetched, for the caller to step through, see
+    /// `link-board replay --step`.
+    Step,
+}
+
+/// A recorded response set and when it was recorded.
+struct Frame {
+    millis: u64,
+    responses: Vec<(Route, String)>,
+}
+
+struct ReplayState {
+    next: usize,
+    /// When the first frame was played and when it was recorded.
+    started: Option<(Instant, u64)>,
+}
+
+/// Plays back a session written by `RecordingRetriever`. Fetches after the last frame never return,
+/// so the board is left showing it, aging as if fetches had stopped.
+pub struct ReplayRetriever {
+    frames: Vec<Frame>,
+    playback: Playback,
+    state: Mutex<ReplayState>,
+}
+
+impl ReplayRetriever {
+    pub fn new(session: &Path, playback: Playback) -> Result<Self, Error> {
+        let mut frames: Vec<Frame> = vec![];
+        let mut files: Vec<(u64, Route, PathBuf)> = vec![];
+        for entry in fs::read_dir(session)? {
+            let path = entry?.path();
+            match parse_file_name(&path) {
+                Some((millis, route)) => files.push((millis, route, path)),
+                None => warn!("ignoring {}, not a recorded response", path.display()),
+            }
+        }
+        files.sort_by_key(|(millis, route, _)| (*millis, line_number(*route)));
+
+        for (millis, route, path) in files {
+            let json = fs::read_to_string(&path)?;
+            match frames.last_mut() {
+                Some(frame) if frame.millis == millis => frame.responses.push((route, json)),
+                _ => frames.push(Frame { millis, responses: vec![(route, json)] }),
+            }
+        }
+        if frames.is_empty() {
+            return Err(Error::io_error(io::Error::new(io::ErrorKind::NotFound, format!("no recorded responses in {}", session.display()))));
+        }
+
+        info!("replaying {} frames from {}", frames.len(), session.display());
+        Ok(Self {
+            frames,
+            playback,
+            state: Mutex::new(ReplayState { next: 0, started: None }),
+        })
+    }
+
+    /// Number of recorded frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The index of the frame the next fetch plays, or `None` if every frame has been played.
+    pub fn next_frame(&self) -> Option<usize> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        (state.next < self.frames.len()).then_some(state.next)
+    }
+
+    /// The index of the frame to play next and how long to wait before playing it, or `None` if
+    /// every frame has been played.
+    fn advance(&self) -> Option<(usize, Duration)> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let idx = state.next;
+        let frame = self.frames.get(idx)?;
+        state.next += 1;
+
+        let wait = match (self.playback, state.started) {
+            (Playback::Speed(factor), Some((start, first_millis))) => {
+                let offset = Duration::from_millis(frame.millis - first_millis).div_f64(factor.max(f64::MIN_POSITIVE));
+                offset.saturating_sub(start.elapsed())
+            },
+            _ => Duration::ZERO,
+        };
+        if state.started.is_none() {
+            state.started = Some((Instant::now(), frame.millis));
+        }
+        Some((idx, wait))
+    }
+}
+
+impl DataRetriever for ReplayRetriever {
+    async fn get_json_for_all_trains(&self) -> Result<Vec<(Route, String)>, Error> {
+        let Some((idx, wait)) = self.advance() else {
+            info!("end of the recording");
+            return future::pending().await;
+        };
+        let frame = &self.frames[idx];
+        tokio::time::sleep(wait).await;
+
+        info!("frame {} of {}, recorded at {} (unix millis)", idx + 1, self.frames.len(), frame.millis);
+        Ok(frame.responses.clone())
+    }
+}
+
+fn parse_file_name(path: &Path) -> Option<(u64, Route)> {
+    let (millis, line) = path.file_name()?.to_str()?.strip_suffix(".json")?.split_once('-')?;
+    Some((millis.parse().ok()?, Route::try_from(line.parse::<u8>().ok()?).ok()?))
+}
+
+fn line_number(route: Route) -> u8 {
+    match route {
+        Route::Line1 => 1,
+        Route::Line2 => 2,
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Responses(Mutex<Vec<Vec<(Route, String)>>>);
+
+    impl DataRetriever for Responses {
+        async fn get_json_for_all_trains(&self) -> Result<Vec<(Route, String)>, Error> {
+            Ok(self.0.lock().unwrap().remove(0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("link-board-recording-{}", std::process::id()));
+        let responses = vec![
+            vec![(Route::Line1, "first 1".to_string()), (Route::Line2, "first 2".to_string())],
+            vec![(Route::Line1, "second 1".to_string())],
+        ];
+        let recorder = RecordingRetriever::new(Responses(Mutex::new(responses.clone())), &dir).unwrap();
+        for expected in &responses {
+            assert_eq!(&recorder.get_json_for_all_trains().await.unwrap(), expected);
+            // frames are told apart by their timestamps
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        let replay = ReplayRetriever::new(&recorder.session, Playback::Speed(1000.0)).unwrap();
+        assert_eq!(replay.len(), 2);
+        for expected in &responses {
+            assert_eq!(&replay.get_json_for_all_trains().await.unwrap(), expected);
+        }
+        // the last frame stays up rather than being fetched over and over
+        assert_eq!(replay.next_frame(), None);
+        assert!(tokio::time::timeout(Duration::from_millis(20), replay.get_json_for_all_trains()).await.is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
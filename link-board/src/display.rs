@@ -1,21 +1,35 @@
 use crate::{
-    constants::{Destination, LED_OFF, STAGING_LED},
-    data_parser,
-    data_retriever::DataRetriever,
-    display::{string_display::StringDisplay, strip_display::StripDisplay},
-    env,
+    config::Config,
+    constants::{Destination, LED_OFF},
+    data_retriever::TrainSource,
+    display::{string_display::StringRenderer, strip_display::StripRenderer},
+    error::Error,
     led::Led,
     spi_adapter::SpiWriter,
+    theme::Theme,
     train::Train
 };
+use std::time::Instant;
 use log::{error, info, warn};
 use colored::Colorize;
-use map_display::MapDisplay;
-use std::str::FromStr;
+use map_display::MapRenderer;
 
+pub use brightness::Brightness;
+pub use compositor::{Compositor, Stage};
+pub use frame::Frame;
+pub use linear_layout::{LinearLayout, Section};
+pub use status::Status;
+pub use train_cache::{DataState, TrainCache};
+
+mod brightness;
+mod compositor;
+mod frame;
+mod linear_layout;
 mod map_display;
+mod status;
 mod string_display;
 mod strip_display;
+mod train_cache;
 
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 pub enum Route {
@@ -24,114 +38,216 @@ pub enum Route {
     Line2,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseRouteErr;
+
+/// Parses a line number, e.g. `1` for the 1 Line.
+impl TryFrom<u8> for Route {
+    type Error = ParseRouteErr;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Route::Line1),
+            2 => Ok(Route::Line2),
+            _ => Err(ParseRouteErr),
+        }
+    }
+}
+
 pub trait LinkBoardDisplay {
     fn update_trains(&mut self, trains: Vec<Train>) -> Result<(), String>;
+    /// Shows trains older than the grace period: dimmed, with the status LED lit if there is one.
+    fn update_stale_trains(&mut self, trains: Vec<Train>) -> Result<(), String>;
+    /// Replaces everything with the pattern for `status`.
+    fn show_status(&mut self, status: Status) -> Result<(), String>;
     fn clear_trains(&mut self);
-    fn init_red(&mut self) -> Result<(), String>;
-    fn get_1n_init_idx(&self) -> usize;
-    fn get_1n_staging_idx(&self) -> usize;
-    fn get_1s_init_idx(&self) -> usize;
-    fn get_1s_staging_idx(&self) -> usize;
+    /// What was last written to the LEDs, if anything has been.
+    fn last_frame(&self) -> Option<&Frame>;
+    /// Whether trains without a next stop can be drawn by where they are.
+    fn places_by_position(&self) -> bool;
+}
+
+/// Draws trains for one kind of board.
+pub trait Renderer {
+    fn total_leds(&self) -> usize;
+    /// LED lit when the trains shown are stale, if the board has one to spare.
+    fn status_led(&self) -> Option<usize>;
+    /// Draws `trains` into `frame`, which has `total_leds` LEDs, all off.
+    fn draw_trains(&self, trains: Vec<Train>, frame: &mut Frame);
+    /// Whether trains without a next stop can be drawn by where they are.
+    fn places_by_position(&self) -> bool {
+        false
+    }
+}
+
+/// Which LEDs stand for a train on a board.
+pub trait TrainLayout {
+    /// The LED for `train` at a station, or else the LEDs it could be on before its next stop,
+    /// nearest the stop first. Empty if the board has nowhere to put it.
+    fn train_leds(&self, train: &Train, config: &Config) -> Result<Vec<usize>, Error>;
+    /// Whether trains on `idx` are about to turn back, so they're drawn in the staging color.
+    fn is_staging(&self, _idx: usize) -> bool {
+        false
+    }
+}
+
+/// A renderer drawing into a compositor.
+struct RenderedDisplay {
+    renderer: Box<dyn Renderer>,
+    compositor: Compositor,
+    theme: Theme,
 }
 
-#[derive(PartialEq)]
-enum DisplayType {
+impl LinkBoardDisplay for RenderedDisplay {
+    fn update_trains(&mut self, trains: Vec<Train>) -> Result<(), String> {
+        info!("updating display");
+        self.renderer.draw_trains(trains, self.compositor.draw());
+        self.compositor.present()
+    }
+
+    fn update_stale_trains(&mut self, trains: Vec<Train>) -> Result<(), String> {
+        info!("updating display with stale trains");
+        let frame = self.compositor.draw();
+        self.renderer.draw_trains(trains, frame);
+        mark_stale(frame, self.renderer.status_led(), self.theme.status.stale);
+        self.compositor.present()
+    }
+
+    fn show_status(&mut self, status: Status) -> Result<(), String> {
+        status.draw(self.compositor.draw(), self.renderer.status_led(), &self.theme.status);
+        self.compositor.present()
+    }
+
+    fn clear_trains(&mut self) {
+        self.compositor.clear();
+    }
+
+    fn last_frame(&self) -> Option<&Frame> {
+        self.compositor.last_frame()
+    }
+
+    fn places_by_position(&self) -> bool {
+        self.renderer.places_by_position()
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DisplayType {
+    #[default]
     StripDisplay,
     StringDisplay,
     MapDisplay,
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct ParseDisplayTypeErr;
+pub struct ParseDisplayTypeErr;
 
-impl FromStr for DisplayType {
-    type Err = ParseDisplayTypeErr;
+impl TryFrom<u8> for DisplayType {
+    type Error = ParseDisplayTypeErr;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parsed = s.parse::<u8>().unwrap_or(0); 
-        match parsed {
-            // 0 is StripDisplay
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(DisplayType::StripDisplay),
             1 => Ok(DisplayType::StringDisplay),
             2 => Ok(DisplayType::MapDisplay),
-            _ => Ok(DisplayType::StripDisplay),
+            _ => Err(ParseDisplayTypeErr),
         }
     }
 }
 
-fn get_display_type() -> DisplayType {
-    match env::display_type_int() {
-        1 => DisplayType::StringDisplay,
-        2 => DisplayType::MapDisplay,
-        _ => DisplayType::StripDisplay
-    }
+/// returns the display selected by `config`, defaulting to StripDisplay
+pub fn get_display(adapter: impl SpiWriter + 'static, config: &Config) -> Result<Box<dyn LinkBoardDisplay>, Error> {
+    let renderer: Box<dyn Renderer> = match config.display_type() {
+        DisplayType::StripDisplay => Box::new(StripRenderer::new(config)),
+        DisplayType::StringDisplay => Box::new(StringRenderer::new(config)),
+        DisplayType::MapDisplay => Box::new(MapRenderer::new(config)?),
+    };
+    let compositor = Compositor::new(adapter, renderer.total_leds()).with_stage(Brightness::new(config));
+    let mut display: Box<dyn LinkBoardDisplay> = Box::new(RenderedDisplay { renderer, compositor, theme: config.theme().clone() });
+    display.show_status(Status::Booting).map_err(Error::display_error)?;
+    Ok(display)
 }
 
-/// returns a StripDisplay or StringDisplay, defaulting to StripDisplay
-pub fn get_display(adapter: impl SpiWriter + 'static) -> Box<dyn LinkBoardDisplay> {
-    let mut display: Box<dyn LinkBoardDisplay> = match get_display_type() {
-        DisplayType::StripDisplay => Box::new(StripDisplay::new(adapter)),
-        DisplayType::StringDisplay => Box::new(StringDisplay::new(adapter)),
-        DisplayType::MapDisplay => Box::new(MapDisplay::new(adapter)),
+/// Fetches the current trains into `cache` and shows them, see `render_cached_trains`.
+pub async fn render_trains(display: &mut Box<dyn LinkBoardDisplay>, train_source: &impl TrainSource, cache: &mut TrainCache) {
+    cache.update(train_source.get_all_trains().await);
+    render_cached_trains(display, cache, Instant::now());
+}
+
+/// Shows the trains in `cache` as of `now`, moved along since they were fetched. If fetches keep
+/// failing, they're dimmed after the grace period and then replaced by the status pattern for the
+/// last error.
+pub fn render_cached_trains(display: &mut Box<dyn LinkBoardDisplay>, cache: &TrainCache, now: Instant) {
+    let (state, trains) = cache.state_at(now);
+    let result = match state {
+        DataState::Waiting => Ok(()),
+        DataState::Fresh => display.update_trains(trains),
+        DataState::Stale => display.update_stale_trains(trains),
+        DataState::NoData(status) => display.show_status(status),
     };
-    display.init_red().unwrap();
-    display
+    if let Err(e) = result {
+        error!("Failed to update trains: {e}");
+    }
 }
 
-pub async fn render_trains(display: &mut Box<dyn LinkBoardDisplay>, data_retriever: &impl DataRetriever) {
-    match data_parser::get_all_trains(data_retriever).await {
-        Ok(trains) => {
-            match display.update_trains(trains) {
-                Err(e) => {
-                    error!("Failed to update trains: {e}");
-                },
-                _ => {}
-            }
-        },
-        Err(e) => {
-            error!("failed to get trains: {e}");
-        }
+/// Dims every LED of `frame` and lights the status LED, if any, in `stale`.
+fn mark_stale(frame: &mut Frame, status_led: Option<usize>, stale: Led) {
+    frame.iter_mut().for_each(|led| *led = led.dimmed());
+    if let Some(led) = status_led.and_then(|idx| frame.get_mut(idx)) {
+        *led = stale;
     }
 }
 
-// TODO: Update to handle 2 Line or remove
-fn index_trains(display: &impl LinkBoardDisplay, led_strip: &mut Vec<Led>, trains: Vec<Train>) -> usize {
+/// Draws each train on the first of its LEDs in `layout`, for boards with one LED per spot.
+fn index_trains(layout: &impl TrainLayout, config: &Config, led_strip: &mut [Led], trains: Vec<Train>) -> usize {
     let mut total = 0;
+    let mut skipped = 0;
 
     for train in trains {
-        if train.route() == Route::Line2 {
-            warn!("skipping 2 Line for now!");
-            continue;
-        }
-
         total += 1;
-        if env::stations_only() && !train.at_station() {
+        if train.is_dropped(config) || (config.stations_only() && !train.at_station()) {
             continue;
         }
 
-        let idx = match train.destination() {
-            Destination::LynnwoodCC => display.get_1n_init_idx() + train.get_relative_idx(),
-            Destination::FederalWayDT => display.get_1s_init_idx() + train.get_relative_idx(),
-            Destination::RedmondDT => todo!(),
+        let idx = match layout.train_leds(&train, config).map(|leds| leds.first().copied()) {
+            Ok(Some(idx)) if idx < led_strip.len() => idx,
+            Ok(Some(idx)) => {
+                warn!("skipping train: index {idx} is past the end of the {} LEDs", led_strip.len());
+                skipped += 1;
+                continue;
+            },
+            Ok(None) => {
+                warn!("skipping train: no LED for {:?} train headed to {:?} with next stop {}", train.route(), train.destination(), train.next_stop_name);
+                skipped += 1;
+                continue;
+            },
+            Err(e) => {
+                warn!("skipping train: {e}");
+                skipped += 1;
+                continue;
+            },
         };
 
         let current_color = led_strip[idx];
-        let final_color = if idx == display.get_1n_staging_idx() || idx == display.get_1s_staging_idx() {
-            STAGING_LED
+        let final_color = if layout.is_staging(idx) {
+            config.theme().staging
+        } else if current_color == LED_OFF {
+            train.get_led_rgb(config)
+        } else if train.at_station() {
+            config.theme().multiple_trains.at_station
         } else {
-            let mut new_color = train.get_led_rgb();
-            if current_color == LED_OFF {
-                new_color
-            } else {
-                new_color.add_tuple((0, 0, 100));
-                new_color
-            }
+            config.theme().multiple_trains.between_stations
         };
         led_strip[idx] = final_color;
 
-        let colorized_dir = if train.destination() == Destination::LynnwoodCC {
-            "(N)".red()
-        } else {
-            "(S)".blue()
+        let colorized_dir = match train.destination() {
+            Destination::LynnwoodCC => match train.route() {
+                Route::Line1 => "(N)".red(),
+                Route::Line2 => "(W)".yellow(),
+            },
+            Destination::FederalWayDT => "(S)".blue(),
+            Destination::RedmondDT => "(E)".green(),
         };
         info!("placing {} {} at index [{:3}]; next stop: {}", 
             colorized_dir,
@@ -141,5 +257,30 @@ fn index_trains(display: &impl LinkBoardDisplay, led_strip: &mut Vec<Led>, train
     }
 
     info!("{} total trains", total);
-    total
-}
\ No newline at end of file
+    if skipped > 0 {
+        warn!("skipped {} trains", skipped);
+    }
+    total - skipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct DisconnectedWriter;
+
+    impl SpiWriter for DisconnectedWriter {
+        fn write_rgb(&mut self, _rgb_vec: Vec<Led>) -> Result<(), String> {
+            Err("disconnected".to_string())
+        }
+
+        fn clear(&mut self, _num_to_clear: usize) {}
+    }
+
+    #[test]
+    fn test_failed_first_write_is_an_error() {
+        let config = Config::from_sources(Some("api_key = \"key\"\n"), |_| None).unwrap();
+        let err = get_display(DisconnectedWriter, &config).err().unwrap();
+        assert_eq!(err.to_string(), "error writing LEDs: disconnected");
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::SystemTime;
+
+use crate::{
+    config::{Config, DEFAULT_BRIGHTNESS, DEFAULT_GAMMA},
+    constants::LED_OFF,
+    display::{Frame, Stage},
+    led::Led,
+    local_time::{local_minutes, HoursRange}
+};
+
+/// Gamma corrects every LED and scales it by the configured brightness, dimmer during the night
+/// hours and off during the quiet hours.
+pub struct Brightness {
+    /// Each channel value gamma corrected, still at full scale.
+    levels: [f32; 256],
+    brightness: f32,
+    night: Option<(HoursRange, f32)>,
+    quiet: Option<HoursRange>,
+    utc_offset_hours: i8,
+    daylight_saving: bool,
+    clock: fn() -> SystemTime,
+}
+
+impl Brightness {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            levels: levels(config.gamma()),
+            brightness: config.brightness(),
+            night: config.night_hours().map(|hours| (hours, config.night_brightness())),
+            quiet: config.quiet_hours(),
+            utc_offset_hours: config.utc_offset_hours(),
+            daylight_saving: config.daylight_saving(),
+            clock: SystemTime::now,
+        }
+    }
+
+    /// Scale for every LED at `minute` since local midnight. The schedule is ignored if the clock
+    /// hasn't been set.
+    fn scale(&self, minute: Option<u16>) -> f32 {
+        match minute {
+            Some(minute) if self.quiet.is_some_and(|quiet| quiet.contains(minute)) => 0.0,
+            Some(minute) => match self.night {
+                Some((night, night_brightness)) if night.contains(minute) => self.brightness * night_brightness,
+                _ => self.brightness,
+            },
+            None => self.brightness,
+        }
+    }
+}
+
+/// The default brightness and gamma, with no schedule, for writing LEDs without a config.
+impl Default for Brightness {
+    fn default() -> Self {
+        Self {
+            levels: levels(DEFAULT_GAMMA),
+            brightness: DEFAULT_BRIGHTNESS,
+            night: None,
+            quiet: None,
+            utc_offset_hours: 0,
+            daylight_saving: false,
+            clock: SystemTime::now,
+        }
+    }
+}
+
+impl Stage for Brightness {
+    fn apply(&mut self, frame: &mut Frame) {
+        let minute = local_minutes((self.clock)(), self.utc_offset_hours, self.daylight_saving);
+        let scale = self.scale(minute);
+        if scale == 0.0 {
+            frame.fill(LED_OFF);
+            return;
+        }
+        let level = |c: u8| (self.levels[c as usize] * scale).round() as u8;
+        frame.iter_mut().for_each(|led| *led = Led::from(level(led.r()), level(led.g()), level(led.b())));
+    }
+}
+
+fn levels(gamma: f32) -> [f32; 256] {
+    std::array::from_fn(|c| (c as f32 / 255.0).powf(gamma) * 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    fn config(extra: &str) -> Config {
+        Config::from_sources(Some(&format!("api_key = \"key\"\nutc_offset_hours = 0\ndaylight_saving = false\n{extra}")), |_| None).unwrap()
+    }
+
+    fn apply(brightness: &mut Brightness, led: Led) -> Led {
+        let mut frame = Frame::from(vec![led]);
+        brightness.apply(&mut frame);
+        frame[0]
+    }
+
+    #[test]
+    fn test_defaults_match_tuned_colors() {
+        let mut brightness = Brightness::new(&config(""));
+        assert!(apply(&mut brightness, Led::red()) == Led::from(30, 0, 0));
+        assert!(apply(&mut brightness, Led::dull_white()) == Led::from(2, 2, 2));
+        assert!(apply(&mut brightness, Led::orange()) == Led::from(20, 30, 0));
+        assert!(apply(&mut brightness, Led::dull_orange()) == Led::from(10, 20, 0));
+        assert!(apply(&mut brightness, Led::dull_cyan()) == Led::from(5, 10, 20));
+        assert!(apply(&mut brightness, Led::dull_purple()) == Led::from(20, 0, 20));
+        assert!(apply(&mut brightness, Led::ln_1_at_station()) == Led::from(61, 174, 43));
+        assert!(apply(&mut brightness, Led::ln_2_between_stations()) == Led::from(0, 16, 22));
+
+        let mut linear = Brightness::new(&config("brightness = 1.0\ngamma = 1.0\n"));
+        assert!(apply(&mut linear, Led::ln_1_at_station()) == Led::ln_1_at_station());
+    }
+
+    #[test]
+    fn test_schedule() {
+        let mut brightness = Brightness::new(&config("brightness = 1.0\ngamma = 1.0\nnight_hours = \"22:00-06:00\"\nnight_brightness = 0.5\nquiet_hours = \"01:00-05:00\"\n"));
+        // 2025-01-15 at 23:00, 03:00 and noon UTC
+        brightness.clock = || UNIX_EPOCH + Duration::from_secs(1_736_982_000);
+        assert!(apply(&mut brightness, Led::from(200, 100, 0)) == Led::from(100, 50, 0));
+        brightness.clock = || UNIX_EPOCH + Duration::from_secs(1_736_910_000);
+        assert!(apply(&mut brightness, Led::from(200, 100, 0)) == LED_OFF);
+        brightness.clock = || UNIX_EPOCH + Duration::from_secs(1_736_942_400);
+        assert!(apply(&mut brightness, Led::from(200, 100, 0)) == Led::from(200, 100, 0));
+        // the schedule waits for the clock to be set
+        brightness.clock = || UNIX_EPOCH;
+        assert!(apply(&mut brightness, Led::from(200, 100, 0)) == Led::from(200, 100, 0));
+    }
+}
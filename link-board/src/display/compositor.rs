@@ -0,0 +1,105 @@
+use crate::{constants::LED_OFF, display::Frame, spi_adapter::SpiWriter};
+
+/// A step each frame goes through after it's drawn and before it's written, like brightness or
+/// gamma correction.
+pub trait Stage {
+    fn apply(&mut self, frame: &mut Frame);
+}
+
+/// Owns the frame displays draw into, and writes it to the LEDs through each `Stage` in turn.
+pub struct Compositor {
+    frame: Frame,
+    stages: Vec<Box<dyn Stage>>,
+    writer: Box<dyn SpiWriter>,
+    last_written: Option<Frame>,
+}
+
+impl Compositor {
+    pub fn new(writer: impl SpiWriter + 'static, len: usize) -> Self {
+        Self {
+            frame: Frame::new(len),
+            stages: vec![],
+            writer: Box::new(writer),
+            last_written: None,
+        }
+    }
+
+    /// Adds `stage` after the ones already added.
+    pub fn with_stage(mut self, stage: impl Stage + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// The frame to draw the next update into, all off.
+    pub fn draw(&mut self) -> &mut Frame {
+        self.frame.fill(LED_OFF);
+        &mut self.frame
+    }
+
+    /// Writes what was drawn, after the stages. The drawn frame itself is left as it was drawn.
+    pub fn present(&mut self) -> Result<(), String> {
+        let mut frame = self.frame.clone();
+        for stage in &mut self.stages {
+            stage.apply(&mut frame);
+        }
+        self.writer.write_rgb(frame.to_vec())?;
+        self.last_written = Some(frame);
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.writer.clear(self.frame.len());
+        self.last_written = Some(Frame::new(self.frame.len()));
+    }
+
+    /// The frame last written to the LEDs, as it left the last stage.
+    pub fn last_frame(&self) -> Option<&Frame> {
+        self.last_written.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::led::Led;
+
+    use super::*;
+
+    /// Fails the write after the first.
+    struct OnceWriter(Rc<Cell<usize>>);
+
+    impl SpiWriter for OnceWriter {
+        fn write_rgb(&mut self, _rgb_vec: Vec<Led>) -> Result<(), String> {
+            self.0.set(self.0.get() + 1);
+            if self.0.get() > 1 { Err("disconnected".to_string()) } else { Ok(()) }
+        }
+
+        fn clear(&mut self, _num_to_clear: usize) {}
+    }
+
+    struct Dim;
+
+    impl Stage for Dim {
+        fn apply(&mut self, frame: &mut Frame) {
+            frame.iter_mut().for_each(|led| *led = led.dimmed());
+        }
+    }
+
+    #[test]
+    fn test_present() {
+        let writes = Rc::new(Cell::new(0));
+        let mut compositor = Compositor::new(OnceWriter(writes.clone()), 3).with_stage(Dim).with_stage(Dim);
+        assert!(compositor.last_frame().is_none());
+
+        compositor.draw()[1] = Led::from(40, 0, 0);
+        compositor.present().unwrap();
+        assert!(compositor.last_frame().unwrap().leds() == [LED_OFF, Led::from(22, 0, 0), LED_OFF]);
+
+        // a failed write leaves the last frame that made it out
+        compositor.draw()[0] = Led::from(40, 0, 0);
+        assert!(compositor.present().is_err());
+        assert_eq!(writes.get(), 2);
+        assert!(compositor.last_frame().unwrap()[0] == LED_OFF);
+    }
+}
@@ -0,0 +1,44 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::{constants::LED_OFF, led::Led};
+
+/// The color of every LED on the board, in strip order.
+#[derive(Clone, PartialEq)]
+pub struct Frame {
+    leds: Vec<Led>,
+}
+
+impl Frame {
+    /// `len` LEDs, all off.
+    pub fn new(len: usize) -> Self {
+        Self { leds: vec![LED_OFF; len] }
+    }
+
+    pub fn leds(&self) -> &[Led] {
+        &self.leds
+    }
+
+    pub fn fill(&mut self, led: Led) {
+        self.leds.fill(led);
+    }
+}
+
+impl From<Vec<Led>> for Frame {
+    fn from(leds: Vec<Led>) -> Self {
+        Self { leds }
+    }
+}
+
+impl Deref for Frame {
+    type Target = [Led];
+
+    fn deref(&self) -> &[Led] {
+        &self.leds
+    }
+}
+
+impl DerefMut for Frame {
+    fn deref_mut(&mut self) -> &mut [Led] {
+        &mut self.leds
+    }
+}
@@ -0,0 +1,94 @@
+//! Layouts for the strip and string displays: a row of LEDs per line and direction, one for each
+//! station and one between each pair of stations.
+
+use crate::{
+    config::Config,
+    constants::{EASTSIDE_STATIONS, PIXELS_FOR_EASTSIDE},
+    display::{Route, TrainLayout},
+    error::Error,
+    layout::Side,
+    train::Train
+};
+
+/// The LEDs for trains on `route` headed to `side`, from `init_idx` for the far end of the line
+/// (Federal Way Downtown or Downtown Redmond), as counted by `Train::get_relative_idx`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Section {
+    pub route: Route,
+    pub side: Side,
+    pub init_idx: usize,
+    /// Number of LEDs from `init_idx`.
+    pub len: usize,
+    /// LED for trains about to turn back, just outside the section.
+    pub staging_idx: usize,
+}
+
+/// Sections of a strip or string display. A line without a section of its own is drawn on the
+/// 1 Line's, which is how `Config::merge_line_2` draws the 2 Line from CID to Lynnwood.
+#[derive(Clone, Debug, Default)]
+pub struct LinearLayout {
+    sections: Vec<Section>,
+}
+
+impl LinearLayout {
+    pub fn new(sections: Vec<Section>) -> Self {
+        Self { sections }
+    }
+
+    pub fn section(&self, route: Route, side: Side) -> Option<&Section> {
+        self.sections.iter().find(|section| section.route == route && section.side == side)
+    }
+}
+
+impl TrainLayout for LinearLayout {
+    fn train_leds(&self, train: &Train, config: &Config) -> Result<Vec<usize>, Error> {
+        let relative_idx = train.get_relative_idx(config)?;
+        let eastside = if config.stations_only() { EASTSIDE_STATIONS } else { PIXELS_FOR_EASTSIDE };
+        let (route, relative_idx) = if train.route() == Route::Line2 && config.merge_line_2() && relative_idx >= eastside {
+            (Route::Line1, train.relative_idx_on(Route::Line1, config)?)
+        } else {
+            (train.route(), relative_idx)
+        };
+
+        let Some(section) = self.section(route, Side::for_destination(train.destination())) else {
+            return Ok(vec![]);
+        };
+        let idx = section.init_idx + relative_idx;
+        if relative_idx < section.len || idx == section.staging_idx {
+            Ok(vec![idx])
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn is_staging(&self, idx: usize) -> bool {
+        self.sections.iter().any(|section| section.staging_idx == idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{constants::Destination, stations::{JUDKINS_PARK, WESTLAKE}};
+
+    use super::*;
+
+    #[test]
+    fn test_train_leds() {
+        // just the Eastside, Lynnwood-bound
+        let layout = LinearLayout::new(vec![
+            Section { route: Route::Line2, side: Side::North, init_idx: 10, len: PIXELS_FOR_EASTSIDE, staging_idx: 10 + PIXELS_FOR_EASTSIDE },
+        ]);
+        let config = |extra| Config::from_sources(Some(&format!("api_key = \"key\"\n{extra}")), |_| None).unwrap();
+        let train = |stop: &str, destination| Train::new(stop.to_string(), Route::Line2, destination, 0, 0);
+
+        let merged = config("merge_line_2 = true\n");
+        assert_eq!(layout.train_leds(&train(JUDKINS_PARK.name, Destination::LynnwoodCC), &merged).unwrap(), vec![10 + 22]);
+        assert!(layout.train_leds(&train(JUDKINS_PARK.name, Destination::RedmondDT), &merged).unwrap().is_empty());
+        // drawn on the 1 Line, which this layout doesn't have
+        assert!(layout.train_leds(&train(WESTLAKE.name, Destination::LynnwoodCC), &merged).unwrap().is_empty());
+        // past the end of the section
+        assert!(layout.train_leds(&train(WESTLAKE.name, Destination::LynnwoodCC), &config("")).unwrap().is_empty());
+        assert!(layout.train_leds(&train("Nowhere", Destination::LynnwoodCC), &merged).is_err());
+        assert!(layout.is_staging(10 + PIXELS_FOR_EASTSIDE));
+    }
+}
@@ -1,231 +1,245 @@
 use std::collections::HashMap;
 use colored::Colorize;
-use log::info;
+use log::{info, warn};
 use priority_queue::PriorityQueue;
 
 use crate::{
-    constants::{Destination, CID, LED_OFF, LED_RED, LN_1_STN_NAME_TO_LED_MAP_IDX, LN_2_STN_NAME_TO_LED_MAP_IDX}, display::LinkBoardDisplay, led::Led, spi_adapter::SpiWriter, train::Train
+    config::Config,
+    constants::{Destination, LED_OFF}, display::{Frame, Renderer, TrainLayout}, error::Error, layout::MapLayout, led::Led, train::Train
 };
 
 use super::Route;
 
-const MAX_LEDS_FOR_STRIP: usize = 302;
+/// Typical run between Link stations, used to place trains without a known `Train::progress` on
+/// the LEDs before their next stop.
+const SEGMENT_SECS: usize = 150;
 
-pub struct MapDisplay {
-    adapter: Box<dyn SpiWriter>
-}
+/// map of `(stop name, Destination, index before next stop)` to the segment's LEDs and
+/// `(Route, Led, progress in thousandths)` prioritised by time offset to the next stop
+type InBetweens = HashMap<(String, Destination, usize), (Vec<usize>, PriorityQueue<(Route, Led, Option<u16>), i64>)>;
 
-impl MapDisplay {
-    pub fn new(adapter: impl SpiWriter + 'static) -> Self {
-        Self {
-            adapter: Box::new(adapter)
-        }
-    }
+pub struct MapRenderer {
+    config: Config,
+    layout: MapLayout,
+}
 
-    #[allow(dead_code)]
-    fn show_static_stations(&mut self) -> Result<(), String> {
-        let mut led_strip: Vec<Led> = vec![LED_OFF; MAX_LEDS_FOR_STRIP];
-        write_stations_as_dim_white(&mut led_strip);
-        self.adapter.write_rgb(led_strip)
+impl MapRenderer {
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        Ok(Self {
+            config: config.clone(),
+            layout: MapLayout::load(config)?,
+        })
     }
 }
 
-impl LinkBoardDisplay for MapDisplay {
-    fn update_trains(&mut self, trains: Vec<Train>) -> Result<(), String> {
-        info!("updating map display");
-
-        let mut led_strip: Vec<Led> = vec![LED_OFF; MAX_LEDS_FOR_STRIP];
-
-        // set stations to purple as a placemarker
-        write_stations_as_dim_white(&mut led_strip);
-
-        index_trains(&mut led_strip, trains);
-
-        self.adapter.write_rgb(led_strip)
+impl Renderer for MapRenderer {
+    fn total_leds(&self) -> usize {
+        self.layout.total_leds()
     }
 
-    fn clear_trains(&mut self) {
-        self.adapter.clear(MAX_LEDS_FOR_STRIP);
+    fn status_led(&self) -> Option<usize> {
+        self.layout.status_led()
     }
 
-    fn init_red(&mut self) -> Result<(), String> {
-        let led_strip = vec![LED_RED; MAX_LEDS_FOR_STRIP];
-        self.adapter.write_rgb(led_strip)
-    }
+    fn draw_trains(&self, trains: Vec<Train>, led_strip: &mut Frame) {
 
-    fn get_1n_init_idx(&self) -> usize {
-        unimplemented!()
-    }
+        // light up stations as a placemarker
+        write_empty_stations(&self.layout, self.config.theme().empty_station, led_strip);
 
-    fn get_1n_staging_idx(&self) -> usize {
-        unimplemented!()
+        index_trains(&self.config, &self.layout, led_strip, trains);
     }
 
-    fn get_1s_init_idx(&self) -> usize {
-        unimplemented!()
+    /// Only a layout with coordinates for its LEDs can place a train by where it is.
+    fn places_by_position(&self) -> bool {
+        !self.layout.geo().is_empty()
     }
+}
 
-    fn get_1s_staging_idx(&self) -> usize {
-        unimplemented!()
+impl TrainLayout for MapLayout {
+    fn train_leds(&self, train: &Train, _config: &Config) -> Result<Vec<usize>, Error> {
+        if !self.contains(train.route(), &train.next_stop_name) {
+            return Err(Error::unknown_stop_error(train.next_stop_name.clone()));
+        }
+        let leds = if train.at_station() {
+            self.station_idx(train.route(), train.destination(), &train.next_stop_name).into_iter().collect()
+        } else {
+            self.segment_leds(train.route(), train.destination(), &train.next_stop_name).unwrap_or_default()
+        };
+        Ok(leds)
     }
 }
 
-fn index_trains(led_strip: &mut Vec<Led>, trains: Vec<Train>) -> usize {
+fn index_trains(config: &Config, layout: &MapLayout, led_strip: &mut [Led], trains: Vec<Train>) -> usize {
+    let theme = config.theme();
     let mut total = 0;
+    let mut skipped = 0;
 
     // map of `(stop name, Destination, index before next stop)` to `Destination prioritised by time offset to destination)`
     // the index is used to differentiate where to place Lynnwood-bound trains headed for the CID station,
     // which is where the 1 and 2 lines merge.
-    let mut in_betweens: HashMap<(String, Destination, usize), PriorityQueue<(Route, Led), i64>> = Default::default();
+    let mut in_betweens: InBetweens = Default::default();
+    // trains placed by where they are, drawn after the rest so they can share their LEDs
+    let mut by_position: Vec<(Train, usize)> = vec![];
 
     for train in trains {
-
-        let mut final_idx = 0;
-        let mut final_color = LED_OFF;
-
-        let base_map_idx = train.get_map_idx();
-        let current_color = if led_strip[base_map_idx] == Led::empty_station() {
-            LED_OFF
-        } else {
-            led_strip[base_map_idx]
+        if train.is_dropped(config) {
+            info!("dropping {:?} train headed to {:?}, its position is {:?} old", train.route(), train.destination(), train.age());
+            continue;
+        }
+        let leds = layout.train_leds(&train, config).unwrap_or_default();
+        if config.geo_placement() || leds.is_empty() {
+            let geo_idx = train.position()
+                .and_then(|(lat, lon)| layout.nearest_led(train.route(), train.destination(), lat, lon));
+            if let Some(idx) = geo_idx {
+                by_position.push((train, idx));
+                total += 1;
+                continue;
+            }
+        }
+        let Some(&base_map_idx) = leds.first() else {
+            warn!("no LED for {:?} train headed to {:?} with next stop {}", train.route(), train.destination(), train.next_stop_name);
+            skipped += 1;
+            continue;
         };
 
         if train.at_station() {
-            final_idx = base_map_idx;
-            if current_color == LED_OFF {
-                final_color = train.get_led_rgb();
-            } else if current_color != train.get_led_rgb() {
-                final_color = Led::at_station_mixed();
-            }
-            log_train_placement(train.destination(), train.route(), &train.next_stop_name, final_idx, &final_color, None);
-        } else {
-            if let Some(pq) = in_betweens.get_mut(&(train.next_stop_name.clone(), train.destination(), train.idx_before_next_stop())) {
-                pq.push((train.route(), train.get_led_rgb()), train.next_stop_time_offset());
+            let current_color = if led_strip[base_map_idx] == theme.empty_station {
+                LED_OFF
             } else {
-                let mut pq = PriorityQueue::new();
-                pq.push((train.route(), train.get_led_rgb()), train.next_stop_time_offset());
-                in_betweens.insert((train.next_stop_name.clone(), train.destination(), train.idx_before_next_stop()), pq.clone());
-            }
+                led_strip[base_map_idx]
+            };
+
+            let final_color = if current_color == LED_OFF || current_color == train.get_led_rgb(config) {
+                train.get_led_rgb(config)
+            } else {
+                theme.multiple_trains.at_station
+            };
+            led_strip[base_map_idx] = final_color;
+            log_train_placement(train.destination(), train.route(), &train.next_stop_name, base_map_idx, &final_color, None);
+        } else {
+            in_betweens.entry((train.next_stop_name.clone(), train.destination(), base_map_idx))
+                .or_insert_with(|| (leds, PriorityQueue::new()))
+                .1
+                .push((train.route(), train.get_led_rgb(config), train.progress().map(permille)), train.next_stop_time_offset());
         }
 
-        // actually write the data
-        led_strip[final_idx] = final_color;
         total += 1;
     }
 
     // handle trains in between stations
-    for ((next_stop_name, destination, idx_before_next_stop), mut queue) in in_betweens {
-        if let Some(((route, _), _)) = queue.peek() {
-            let route = route.clone();
-            let leds_between_stops = num_leds_between_stops(route, destination, &next_stop_name);
-
-            if leds_between_stops >= queue.len() {
-                // easy case: enough leds available to handle all trains
-                let mut idx = idx_before_next_stop;
-                while !queue.is_empty() {
-                    if let Some(((route, led), _)) = queue.pop() {
-                        led_strip[idx] = led;
-                        log_train_placement(destination, route, &next_stop_name, idx, &led_strip[idx], None);
-                        idx = get_next_inbetween_idx(idx, route, destination, &next_stop_name);
-                    }
-                }
-            } else if queue.len() >= leds_between_stops * 2 {
-                // other easy case: every spot will be at least doubled, so just color them all with the 
-                // 'doubled spot' color
-                for idx in idx_before_next_stop..(idx_before_next_stop + leds_between_stops) {
-                    led_strip[idx] = Led::between_stations_mixed();
-                    log_train_placement(destination, route, &next_stop_name, idx, &led_strip[idx], Some(" [doubled]"));
-                }
-            } else {
-                // hard case: there are more trains than available spots, but not all spots need to be doubled
-                let mut num_trains = queue.len();
-                let excess_trains = num_trains % leds_between_stops;
-                let single_color_trains = leds_between_stops - excess_trains;
-                assert_eq!(num_trains, excess_trains + single_color_trains);
-
-                // fill up initial LEDs
-                let range = match destination {
-                    Destination::LynnwoodCC => match route {
-                        Route::Line1 => idx_before_next_stop..(idx_before_next_stop + single_color_trains),
-                        Route::Line2 => {
-                            if LN_1_STN_NAME_TO_LED_MAP_IDX.contains_key(&next_stop_name) {
-                                idx_before_next_stop..(idx_before_next_stop + single_color_trains)
-                            } else {
-                                idx_before_next_stop..(idx_before_next_stop - single_color_trains)
-                            }
-                        },
-                    },
-                    Destination::FederalWayDT => idx_before_next_stop..(idx_before_next_stop + single_color_trains),
-                    Destination::RedmondDT => {
-                        if LN_1_STN_NAME_TO_LED_MAP_IDX.contains_key(&next_stop_name) {
-                            idx_before_next_stop..(idx_before_next_stop + single_color_trains)
-                        } else {
-                            idx_before_next_stop..(idx_before_next_stop - single_color_trains)
-                        }
-                    },
-                };
-                for idx in range {
-                    if let Some(((_, led), _)) = queue.pop() {
-                        led_strip[idx] = led;
-                        num_trains = num_trains - 1;
-                        log_train_placement(destination, route, &next_stop_name, idx, &led_strip[idx], None);
-                    }
-                }
-
-                // fill the rest with the doubled-up color
-                let range = match destination {
-                    Destination::LynnwoodCC => match route {
-                        Route::Line1 => (idx_before_next_stop + single_color_trains)..(idx_before_next_stop + leds_between_stops),
-                        Route::Line2 => {
-                            if LN_1_STN_NAME_TO_LED_MAP_IDX.contains_key(&next_stop_name) {
-                                (idx_before_next_stop + single_color_trains)..(idx_before_next_stop + leds_between_stops)
-                            } else {
-                                (idx_before_next_stop - single_color_trains)..(idx_before_next_stop - leds_between_stops)
-                            }
-                        },
-                    },
-                    Destination::FederalWayDT => (idx_before_next_stop + single_color_trains)..(idx_before_next_stop + leds_between_stops),
-                    Destination::RedmondDT => {
-                        if LN_1_STN_NAME_TO_LED_MAP_IDX.contains_key(&next_stop_name) {
-                            (idx_before_next_stop + single_color_trains)..(idx_before_next_stop + leds_between_stops)
-                        } else {
-                            (idx_before_next_stop - single_color_trains)..(idx_before_next_stop - leds_between_stops)
-                        }
-                    },
-                };
-                for idx in range {
-                    led_strip[idx] = Led::between_stations_mixed();
-                    log_train_placement(destination, route, &next_stop_name, idx, &led_strip[idx], Some(" [doubled]"));
+    for ((next_stop_name, destination, _), (segment, mut queue)) in in_betweens {
+        let Some(((route, _, _), _)) = queue.peek() else {
+            continue;
+        };
+        let route = *route;
+
+        if segment.len() >= queue.len() {
+            // easy case: enough leds available to handle all trains, so each one goes where its
+            // distance along the trip, or else its time to the next stop, puts it
+            let mut nearest_first: Vec<(usize, (Route, Led))> = queue.into_sorted_iter()
+                .map(|((route, led, progress), offset)| {
+                    let wanted = match progress {
+                        Some(progress) => progress_position(progress, segment.len()),
+                        None => segment_position(offset, segment.len()),
+                    };
+                    (wanted, (route, led))
+                })
+                .collect();
+            nearest_first.reverse();
+            nearest_first.sort_by_key(|(wanted, _)| *wanted);
+            let wanted = nearest_first.iter().map(|(wanted, _)| *wanted);
+            for ((_, (route, led)), slot) in nearest_first.iter().zip(spread(wanted, segment.len())) {
+                let idx = segment[slot];
+                led_strip[idx] = *led;
+                log_train_placement(destination, *route, &next_stop_name, idx, &led_strip[idx], None);
+            }
+        } else if queue.len() >= segment.len() * 2 {
+            // other easy case: every spot will be at least doubled, so just color them all with the
+            // 'doubled spot' color
+            for &idx in &segment {
+                led_strip[idx] = theme.multiple_trains.between_stations;
+                log_train_placement(destination, route, &next_stop_name, idx, &led_strip[idx], Some(" [doubled]"));
+            }
+        } else {
+            // hard case: there are more trains than available spots, but not all spots need to be doubled
+            let excess_trains = queue.len() % segment.len();
+            let single_color_trains = segment.len() - excess_trains;
+            let (singles, doubles) = segment.split_at(single_color_trains);
+
+            // fill up initial LEDs
+            for &idx in singles {
+                if let Some(((route, led, _), _)) = queue.pop() {
+                    led_strip[idx] = led;
+                    log_train_placement(destination, route, &next_stop_name, idx, &led_strip[idx], None);
                 }
             }
 
+            // fill the rest with the doubled-up color
+            for &idx in doubles {
+                led_strip[idx] = theme.multiple_trains.between_stations;
+                log_train_placement(destination, route, &next_stop_name, idx, &led_strip[idx], Some(" [doubled]"));
+            }
         }
     }
 
+    for (train, idx) in by_position {
+        let led = train.get_led_rgb(config);
+        let current = led_strip[idx];
+        led_strip[idx] = if current == LED_OFF || current == theme.empty_station || current == led {
+            led
+        } else if train.at_station() {
+            theme.multiple_trains.at_station
+        } else {
+            theme.multiple_trains.between_stations
+        };
+        log_train_placement(train.destination(), train.route(), &train.next_stop_name, idx, &led_strip[idx], Some(" [by position]"));
+    }
+
     info!("placed {} trains total", total);
+    if skipped > 0 {
+        warn!("skipped {} trains", skipped);
+    }
 
     total
 }
 
-fn num_leds_between_stops(route: Route, destination: Destination, next_stop_name: &str) -> usize {
-    match destination {
-        Destination::LynnwoodCC => match route {
-            Route::Line1 => LN_1_STN_NAME_TO_LED_MAP_IDX[next_stop_name].1.1,
-            Route::Line2 => LN_2_STN_NAME_TO_LED_MAP_IDX[next_stop_name].1.1,
-        },
-        Destination::FederalWayDT => LN_1_STN_NAME_TO_LED_MAP_IDX[next_stop_name].0.1,
-        Destination::RedmondDT => LN_2_STN_NAME_TO_LED_MAP_IDX[next_stop_name].0.1,
-    }
+/// Position in a segment of `leds` LEDs, counted from the station, of a train `offset` seconds
+/// from it, assuming the trip between stations takes `SEGMENT_SECS`.
+fn segment_position(offset: i64, leds: usize) -> usize {
+    let offset = usize::try_from(offset).unwrap_or(0);
+    (offset * leds / SEGMENT_SECS).min(leds.saturating_sub(1))
+}
+
+/// Position in a segment of `leds` LEDs, counted from the station, of a train `progress`
+/// thousandths of the way there from the previous stop.
+fn progress_position(progress: u16, leds: usize) -> usize {
+    let remaining = usize::from(1000u16.saturating_sub(progress));
+    (remaining * leds / 1000).min(leds.saturating_sub(1))
+}
+
+fn permille(progress: f64) -> u16 {
+    (progress.clamp(0.0, 1.0) * 1000.0).round() as u16
 }
 
-fn write_stations_as_dim_white(led_strip: &mut Vec<Led>) {
-    for (_, v) in LN_2_STN_NAME_TO_LED_MAP_IDX.entries() {
-        led_strip[v.0.0] = Led::empty_station();
-        led_strip[v.1.0] = Led::empty_station();
+/// Moves the `wanted` positions, sorted nearest the station first, apart so no two trains share an
+/// LED of the `leds` available. There must be at least as many LEDs as trains.
+fn spread(wanted: impl Iterator<Item = usize>, leds: usize) -> Vec<usize> {
+    let mut slots: Vec<usize> = vec![];
+    for slot in wanted {
+        slots.push(slots.last().map_or(slot, |&prev| slot.max(prev + 1)));
+    }
+    // trains pushed off the end push the ones before them back toward the station
+    let mut last = leds;
+    for slot in slots.iter_mut().rev() {
+        *slot = (*slot).min(last - 1);
+        last = *slot;
     }
-    for (_, v) in LN_1_STN_NAME_TO_LED_MAP_IDX.entries() {
-        led_strip[v.0.0] = Led::empty_station();
-        led_strip[v.1.0] = Led::empty_station();
+    slots
+}
+
+fn write_empty_stations(layout: &MapLayout, empty_station: Led, led_strip: &mut [Led]) {
+    for (_, idx) in layout.all_station_idxs() {
+        led_strip[idx] = empty_station;
     }
 }
 
@@ -254,37 +268,72 @@ fn log_train_placement(
         "train".truecolor(r, g, b),
         idx,
         next_stop_name,
-        if let Some(msg) = optional_message {
-            msg
-        } else {
-            ""
-        }
+        optional_message.unwrap_or_default()
     );
 }
 
-fn get_next_inbetween_idx(idx: usize, route: Route, destination: Destination, next_stop_name: &String) -> usize {
-    match destination {
-        Destination::LynnwoodCC => match route {
-            Route::Line1 => idx + 1,
-            Route::Line2 => {
-                if LN_1_STN_NAME_TO_LED_MAP_IDX.contains_key(&next_stop_name) {
-                    if next_stop_name == CID {
-                        idx - 1
-                    } else {
-                        idx + 1
-                    }
-                } else {
-                    idx - 1
-                }    
-            },
-        },
-        Destination::FederalWayDT => idx + 1,
-        Destination::RedmondDT => {
-            if LN_1_STN_NAME_TO_LED_MAP_IDX.contains_key(&next_stop_name) {
-                idx + 1
-            } else {
-                idx - 1
-            }
-        },
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_position() {
+        assert_eq!(segment_position(0, 4), 0);
+        assert_eq!(segment_position(40, 4), 1);
+        assert_eq!(segment_position(149, 4), 3);
+        assert_eq!(segment_position(600, 4), 3);
+        assert_eq!(segment_position(-10, 4), 0);
+    }
+
+    #[test]
+    fn test_progress_position() {
+        // 10 LEDs from SeaTac to Tukwila, counted from Tukwila
+        assert_eq!(progress_position(0, 10), 9);
+        assert_eq!(progress_position(permille(0.25), 10), 7);
+        assert_eq!(progress_position(permille(0.95), 10), 0);
+        assert_eq!(progress_position(1000, 10), 0);
+    }
+
+    #[test]
+    fn test_spread() {
+        assert_eq!(spread([1, 3].into_iter(), 4), vec![1, 3]);
+        assert_eq!(spread([0, 0, 2].into_iter(), 4), vec![0, 1, 2]);
+        assert_eq!(spread([3, 3, 3].into_iter(), 4), vec![1, 2, 3]);
+        assert_eq!(spread([0, 3, 3, 3].into_iter(), 4), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_geo_placement() {
+        let toml = r#"
+            total_leds = 8
+            line_1 = [
+                { station = "Symphony", south = [6, 0], north = [5, 0] },
+                { station = "Westlake", south = [7, 0], north = [2, 2] },
+            ]
+            line_2 = []
+            geo = [
+                { led = 2, lat = 47.6114, lon = -122.3372 },
+                { led = 3, lat = 47.6104, lon = -122.3363 },
+                { led = 4, lat = 47.6094, lon = -122.3355 },
+                { led = 5, lat = 47.6080, lon = -122.3360 },
+            ]
+        "#;
+        let layout = MapLayout::from_toml_str(toml).unwrap();
+        let train = |stop: &str| Train::new(stop.to_string(), Route::Line1, Destination::LynnwoodCC, 100, 100)
+            .with_position(Some((47.6103, -122.3362)));
+        let place = |config: &str, trains| {
+            let config = Config::from_sources(Some(&format!("api_key = \"key\"\n{config}")), |_| None).unwrap();
+            let mut led_strip = vec![LED_OFF; layout.total_leds()];
+            index_trains(&config, &layout, &mut led_strip, trains);
+            led_strip
+        };
+
+        // a train without a known next stop falls back to its position
+        assert!(place("", vec![train("")])[3] == Led::ln_1_between_stations());
+        // by its time to the next stop it's further from Westlake than it really is
+        assert!(place("", vec![train("Westlake")])[4] == Led::ln_1_between_stations());
+        assert!(place("geo_placement = true\n", vec![train("Westlake")])[3] == Led::ln_1_between_stations());
+        let shared = place("geo_placement = true\n", vec![train(""), train("").with_position(Some((47.6104, -122.3363)))]);
+        assert!(shared[3] == Led::ln_1_between_stations());
     }
-}
\ No newline at end of file
+}
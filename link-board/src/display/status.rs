@@ -0,0 +1,106 @@
+use crate::{
+    constants::LED_OFF,
+    display::Frame,
+    error::Error,
+    led::Led,
+    theme::StatusColors
+};
+
+/// What the board is doing when it has no trains to show, each shown with its own pattern so the
+/// reason can be read off the board. See "Status patterns" in the README. The colors are the
+/// default theme's.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// Every LED red.
+    Booting,
+    /// Every 4th LED blue. Only shown on the ESP32.
+    ConnectingWifi,
+    /// Every 8th LED dull orange: timeouts, connection errors and HTTP errors.
+    FetchFailed,
+    /// Every 8th LED dull purple: a response arrived but couldn't be parsed.
+    ParseFailed,
+    /// Every 2nd LED red: the server rejected the API key.
+    ApiKeyRejected,
+}
+
+impl Status {
+    fn color(self, colors: &StatusColors) -> Led {
+        match self {
+            Status::Booting => colors.booting,
+            Status::ConnectingWifi => colors.connecting_wifi,
+            Status::FetchFailed => colors.fetch_failed,
+            Status::ParseFailed => colors.parse_failed,
+            Status::ApiKeyRejected => colors.api_key_rejected,
+        }
+    }
+
+    fn spacing(self) -> usize {
+        match self {
+            Status::Booting => 1,
+            Status::ApiKeyRejected => 2,
+            Status::ConnectingWifi => 4,
+            Status::FetchFailed | Status::ParseFailed => 8,
+        }
+    }
+
+    /// Draws the pattern over all of `frame`, with the status LED, if any, in the same color.
+    pub(super) fn draw(self, frame: &mut Frame, status_led: Option<usize>, colors: &StatusColors) {
+        let color = self.color(colors);
+        for (idx, led) in frame.iter_mut().enumerate() {
+            *led = if idx % self.spacing() == 0 { color } else { LED_OFF };
+        }
+        if let Some(led) = status_led.and_then(|idx| frame.get_mut(idx)) {
+            *led = color;
+        }
+    }
+}
+
+impl From<&Error> for Status {
+    fn from(err: &Error) -> Self {
+        if err.is_api_key_rejected() {
+            Status::ApiKeyRejected
+        } else if err.is_parse_error() {
+            Status::ParseFailed
+        } else {
+            Status::FetchFailed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::ApiErr, theme::{Theme, BUILT_IN_THEMES}};
+
+    use super::*;
+
+    #[test]
+    fn test_status_for_error() {
+        let code = |code| Error::api_error(ApiErr::Code { code, text: String::new() });
+        assert_eq!(Status::from(&Error::http_status_error(401, "")), Status::ApiKeyRejected);
+        assert_eq!(Status::from(&code(401)), Status::ApiKeyRejected);
+        assert_eq!(Status::from(&code(500)), Status::FetchFailed);
+        assert_eq!(Status::from(&Error::http_status_error(503, "")), Status::FetchFailed);
+        assert_eq!(Status::from(&Error::api_error(ApiErr::MissingData)), Status::ParseFailed);
+        let json_err = serde_json::from_str::<u8>("{").unwrap_err();
+        assert_eq!(Status::from(&Error::json_error(json_err)), Status::ParseFailed);
+    }
+
+    #[test]
+    fn test_patterns_are_distinct() {
+        let statuses = [Status::Booting, Status::ConnectingWifi, Status::FetchFailed, Status::ParseFailed, Status::ApiKeyRejected];
+        for theme in BUILT_IN_THEMES {
+            let colors = Theme::load(theme).unwrap().status;
+            let frames: Vec<Frame> = statuses.iter()
+                .map(|status| {
+                    let mut frame = Frame::new(16);
+                    status.draw(&mut frame, Some(3), &colors);
+                    frame
+                })
+                .collect();
+            for (i, frame) in frames.iter().enumerate() {
+                assert!(frame[3] == statuses[i].color(&colors));
+                assert!(frames[i + 1..].iter().all(|other| other != frame), "{theme}: {:?} looks like another status", statuses[i]);
+            }
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use crate::{
-    constants::{LED_OFF, LED_RED, PIXELS_FOR_STATIONS},
-    led::Led,
-    display::{index_trains, LinkBoardDisplay},
-    spi_adapter::SpiWriter,
+    config::Config,
+    constants::{PIXELS_FOR_EASTSIDE, PIXELS_FOR_LINE_2, PIXELS_FOR_STATIONS},
+    display::{index_trains, Frame, LinearLayout, Renderer, Route, Section},
+    layout::Side,
     train::Train
 };
 use log::info;
@@ -15,53 +15,58 @@ const SOUTH_TRAIN_INIT_IDX: usize = NORTH_TRAIN_STAGING_IDX + 1;
 // Angle Lake - 1
 const SOUTH_TRAIN_STAGING_IDX: usize = SOUTH_TRAIN_INIT_IDX + PIXELS_FOR_STATIONS;
 
-const MAX_LEDS_NEEDED: usize = SOUTH_TRAIN_STAGING_IDX + 1;
+const LINE_1_LEDS: usize = SOUTH_TRAIN_STAGING_IDX + 1;
 
-pub struct StringDisplay {
-    adapter: Box<dyn SpiWriter>
+// The 2 Line follows, laid out the same way: Downtown Redmond to Lynnwood City Center for
+// Lynnwood-bound trains, then staging, then Redmond-bound trains. With `merge_line_2` it only has
+// the Eastside stations, and trains from CID on are drawn with the 1 Line.
+const LINE_2_NORTH_TRAIN_INIT_IDX: usize = LINE_1_LEDS;
+
+pub struct StringRenderer {
+    config: Config,
+    layout: LinearLayout,
+    total_leds: usize,
 }
 
-impl StringDisplay {
-    pub fn new(adapter: impl SpiWriter + 'static) -> Self {
+impl StringRenderer {
+    pub fn new(config: &Config) -> Self {
+        let mut sections = vec![
+            Section { route: Route::Line1, side: Side::North, init_idx: NORTH_TRAIN_INIT_IDX, len: PIXELS_FOR_STATIONS, staging_idx: NORTH_TRAIN_STAGING_IDX },
+            Section { route: Route::Line1, side: Side::South, init_idx: SOUTH_TRAIN_INIT_IDX, len: PIXELS_FOR_STATIONS, staging_idx: SOUTH_TRAIN_STAGING_IDX },
+        ];
+        let mut total_leds = LINE_1_LEDS;
+        // the 2 Line section is only there if its trains are fetched
+        if config.routes().contains(&Route::Line2) {
+            let len = if config.merge_line_2() { PIXELS_FOR_EASTSIDE } else { PIXELS_FOR_LINE_2 };
+            let north_staging_idx = LINE_2_NORTH_TRAIN_INIT_IDX + len;
+            let south_init_idx = north_staging_idx + 1;
+            sections.push(Section { route: Route::Line2, side: Side::North, init_idx: LINE_2_NORTH_TRAIN_INIT_IDX, len, staging_idx: north_staging_idx });
+            sections.push(Section { route: Route::Line2, side: Side::South, init_idx: south_init_idx, len, staging_idx: south_init_idx + len });
+            total_leds = south_init_idx + len + 1;
+        }
+
         Self {
-            adapter: Box::new(adapter)
+            config: config.clone(),
+            layout: LinearLayout::new(sections),
+            total_leds,
         }
     }
 }
 
-impl LinkBoardDisplay for StringDisplay {
-    fn update_trains(&mut self, trains: Vec<Train>) -> Result<(), String> {
-        let mut led_strip: Vec<Led> = vec![LED_OFF; MAX_LEDS_NEEDED];
-        let mut count = 0;
-
-        count += index_trains(self, &mut led_strip, trains);
-        info!("expecting {} leds", count);
-
-        self.adapter.write_rgb(led_strip)
-    }
-
-    fn clear_trains(&mut self) {
-        self.adapter.clear(MAX_LEDS_NEEDED);
+// Every LED can show a train, so there's no status LED; stale trains are only dimmed.
+impl Renderer for StringRenderer {
+    fn total_leds(&self) -> usize {
+        self.total_leds
     }
 
-    fn init_red(&mut self) -> Result<(), String> {
-        let led_strip: Vec<Led> = vec![LED_RED; MAX_LEDS_NEEDED];
-        self.adapter.write_rgb(led_strip)
+    fn status_led(&self) -> Option<usize> {
+        None
     }
 
-    fn get_1n_init_idx(&self) -> usize {
-        NORTH_TRAIN_INIT_IDX
-    }
-
-    fn get_1n_staging_idx(&self) -> usize {
-        NORTH_TRAIN_STAGING_IDX
-    }
-
-    fn get_1s_init_idx(&self) -> usize {
-        SOUTH_TRAIN_INIT_IDX
-    }
+    fn draw_trains(&self, trains: Vec<Train>, led_strip: &mut Frame) {
+        let mut count = 0;
 
-    fn get_1s_staging_idx(&self) -> usize {
-        SOUTH_TRAIN_STAGING_IDX
+        count += index_trains(&self.layout, &self.config, led_strip, trains);
+        info!("expecting {} leds", count);
     }
-}
\ No newline at end of file
+}
@@ -1,35 +1,34 @@
 use crate::{
-    constants::{LED_OFF, LED_RED, PIXELS_FOR_STATIONS},
+    config::Config,
+    constants::{LED_OFF, PIXELS_FOR_EASTSIDE, PIXELS_FOR_LINE_2, PIXELS_FOR_STATIONS},
     led::Led,
-    display::{index_trains, LinkBoardDisplay},
-    spi_adapter::SpiWriter,
+    display::{index_trains, Frame, LinearLayout, Renderer, Route, Section},
+    layout::Side,
     train::Train
 };
 use log::{info, warn};
 
 const MAX_LEDS_FOR_STRIP: usize = 144;
 const LED_BUFFER_COUNT: usize = 3;
-const START_BUF_LED: Led = Led::red();
-const MID_BUF_LED: Led = Led::orange();
-const END_BUF_LED: Led = Led::blue();
 
 // First three LEDs are start buffer (red).
 //
-// Next 45 LEDs are for northbound trains, starting with Angle Lake station,
+// Next 53 LEDs (`PIXELS_FOR_STATIONS`) are for northbound trains, starting with Angle Lake station,
 // second to last is Lynnwood City Center, last is staging (purple) for train
 // about to return south.
 //
 // Three LEDs for mid buffer (orange).
 //
-// Next 45 LEDs are for southbound trains, starting with staging (purple) for
+// Next 53 LEDs are for southbound trains, starting with staging (purple) for
 // train about to return north, then Angle Lake, ending with Lynnwood City Center.
 //
 // Three LEDs for end buffer (blue).
 //
 // For north and southbound train sections, LEDs alternate between at station
-// (green), and in between stations (yellow). If more than one train is in one
-// of those sections, 10 blue is added, making at-station LEDs more teal, and in
-// between stations more purple.
+// (green), and in between stations (dim green). If more than one train is on one
+// of those LEDs, it's drawn in the theme's multiple trains color.
+//
+// The colors in parentheses are the default theme's, see `theme::Theme`.
 //
 // Both north and southbound sections have Angle Lake (southernmost station) on
 // the left, and Lynwood City Center (northernmost station) on the right.
@@ -48,70 +47,88 @@ const SOUTH_TRAIN_INIT_IDX: usize = SOUTH_TRAIN_STAGING_IDX + 1;
 const END_BUF_INIT_IDX: usize = SOUTH_TRAIN_INIT_IDX + PIXELS_FOR_STATIONS;
 
 const MAX_LEDS_NEEDED: usize = END_BUF_INIT_IDX + LED_BUFFER_COUNT;
-
-pub struct StripDisplay {
-    adapter: Box<dyn SpiWriter>
+const _: () = assert!(MAX_LEDS_NEEDED <= MAX_LEDS_FOR_STRIP);
+
+// The 2 Line gets a second 144 LED strip chained after the first, laid out the same way: Downtown
+// Redmond to Lynnwood City Center in place of Angle Lake to Lynnwood. So with the 2 Line in
+// `routes`, as it is by default, the display needs 288 LEDs. With `merge_line_2` it only has the
+// Eastside stations, and trains from CID on are drawn on the first strip with the 1 Line.
+const LINE_2_START_BUF_INIT_IDX: usize = MAX_LEDS_FOR_STRIP;
+const LINE_2_NORTH_TRAIN_INIT_IDX: usize = LINE_2_START_BUF_INIT_IDX + LED_BUFFER_COUNT;
+const _: () = assert!(LED_BUFFER_COUNT * 3 + PIXELS_FOR_LINE_2 * 2 + 2 <= MAX_LEDS_FOR_STRIP);
+
+pub struct StripRenderer {
+    config: Config,
+    layout: LinearLayout,
+    total_leds: usize,
 }
 
-impl StripDisplay {
-    pub fn new(adapter: impl SpiWriter + 'static) -> Self {
-        assert!(MAX_LEDS_NEEDED <= MAX_LEDS_FOR_STRIP);
+// The first start buffer LED doubles as the status LED.
+const STATUS_LED_IDX: usize = START_BUF_INIT_IDX;
+
+impl StripRenderer {
+    pub fn new(config: &Config) -> Self {
+        let mut sections = vec![
+            Section { route: Route::Line1, side: Side::North, init_idx: NORTH_TRAIN_INIT_IDX, len: PIXELS_FOR_STATIONS, staging_idx: NORTH_TRAIN_STAGING_IDX },
+            Section { route: Route::Line1, side: Side::South, init_idx: SOUTH_TRAIN_INIT_IDX, len: PIXELS_FOR_STATIONS, staging_idx: SOUTH_TRAIN_STAGING_IDX },
+        ];
+        // the second strip is only written if 2 Line trains are fetched
+        let has_line_2 = config.routes().contains(&Route::Line2);
+        if has_line_2 {
+            let len = if config.merge_line_2() { PIXELS_FOR_EASTSIDE } else { PIXELS_FOR_LINE_2 };
+            let north_staging_idx = LINE_2_NORTH_TRAIN_INIT_IDX + len;
+            // past the mid buffer and the staging LED before it
+            let south_init_idx = north_staging_idx + 1 + LED_BUFFER_COUNT + 1;
+            sections.push(Section { route: Route::Line2, side: Side::North, init_idx: LINE_2_NORTH_TRAIN_INIT_IDX, len, staging_idx: north_staging_idx });
+            sections.push(Section { route: Route::Line2, side: Side::South, init_idx: south_init_idx, len, staging_idx: south_init_idx - 1 });
+        }
+
         Self {
-            adapter: Box::new(adapter)
+            config: config.clone(),
+            layout: LinearLayout::new(sections),
+            total_leds: if has_line_2 { MAX_LEDS_FOR_STRIP * 2 } else { MAX_LEDS_FOR_STRIP },
         }
     }
 }
 
-impl LinkBoardDisplay for StripDisplay {
-    fn update_trains(&mut self, trains: Vec<Train>) -> Result<(), String> {
-        let mut led_strip: Vec<Led> = vec![LED_OFF; MAX_LEDS_FOR_STRIP];
+impl Renderer for StripRenderer {
+    fn total_leds(&self) -> usize {
+        self.total_leds
+    }
+
+    fn status_led(&self) -> Option<usize> {
+        Some(STATUS_LED_IDX)
+    }
+
+    fn draw_trains(&self, trains: Vec<Train>, led_strip: &mut Frame) {
+        let buffers = self.config.theme().buffers;
         let mut count = 0;
 
         // write initial leds
         info!("START BUFFER");
-        count += prepare_buffer_leds(&mut led_strip, START_BUF_INIT_IDX, START_BUF_LED);
+        count += prepare_buffer_leds(led_strip, START_BUF_INIT_IDX, buffers.start);
 
-        count += index_trains(self, &mut led_strip, trains);
+        count += index_trains(&self.layout, &self.config, led_strip, trains);
 
         // write mid buffer LEDs
         info!("MID BUFFER");
-        count += prepare_buffer_leds(&mut led_strip, MID_BUF_INIT_IDX, MID_BUF_LED);
+        count += prepare_buffer_leds(led_strip, MID_BUF_INIT_IDX, buffers.mid);
 
         // write end buffer LEDs
         info!("END BUFFER");
-        count += prepare_buffer_leds(&mut led_strip, END_BUF_INIT_IDX, END_BUF_LED);
-        info!("expecting {} leds", count);
-        
-        self.adapter.write_rgb(led_strip)
-    }
-
-    fn clear_trains(&mut self) {
-        self.adapter.clear(MAX_LEDS_FOR_STRIP);
-    }
-
-    fn init_red(&mut self) -> Result<(), String> {
-        let led_strip: Vec<Led> = vec![LED_RED; MAX_LEDS_FOR_STRIP];
-        self.adapter.write_rgb(led_strip)
-    }
-
-    fn get_1n_init_idx(&self) -> usize {
-        NORTH_TRAIN_INIT_IDX
-    }
+        count += prepare_buffer_leds(led_strip, END_BUF_INIT_IDX, buffers.end);
 
-    fn get_1n_staging_idx(&self) -> usize {
-        NORTH_TRAIN_STAGING_IDX
-    }
-
-    fn get_1s_init_idx(&self) -> usize {
-        SOUTH_TRAIN_INIT_IDX
-    }
-
-    fn get_1s_staging_idx(&self) -> usize {
-        SOUTH_TRAIN_STAGING_IDX
+        if let (Some(north), Some(south)) = (self.layout.section(Route::Line2, Side::North), self.layout.section(Route::Line2, Side::South)) {
+            info!("2 LINE BUFFERS");
+            count += prepare_buffer_leds(led_strip, LINE_2_START_BUF_INIT_IDX, buffers.start);
+            count += prepare_buffer_leds(led_strip, north.staging_idx + 1, buffers.mid);
+            count += prepare_buffer_leds(led_strip, south.init_idx + south.len, buffers.end);
+        }
+        info!("expecting {} leds", count);
     }
 }
 
-fn prepare_buffer_leds(led_strip: &mut Vec<Led>, init_idx: usize, led_val: Led) -> usize {
+fn prepare_buffer_leds(led_strip: &mut [Led], init_idx: usize, led_val: Led) -> usize {
     let mut count_written = 0;
     for i in 0..LED_BUFFER_COUNT {
         let idx = init_idx + i;
@@ -123,4 +140,46 @@ fn prepare_buffer_leds(led_strip: &mut Vec<Led>, init_idx: usize, led_val: Led)
         count_written += 1;
     }
     count_written
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::{constants::Destination, stations::{self, JUDKINS_PARK, WESTLAKE}, theme::Theme};
+
+    use super::*;
+
+    fn strip(extra: &str) -> StripRenderer {
+        let config = Config::from_sources(Some(&format!("api_key = \"key\"\n{extra}")), |_| None).unwrap();
+        StripRenderer::new(&config)
+    }
+
+    fn draw(strip: &StripRenderer, trains: Vec<Train>) -> Frame {
+        let mut frame = Frame::new(strip.total_leds());
+        strip.draw_trains(trains, &mut frame);
+        frame
+    }
+
+    #[test]
+    fn test_line_2_sections() {
+        let at = |name: &str| Train::new(name.to_string(), Route::Line2, Destination::LynnwoodCC, 0, 0);
+        let trains = || vec![at(WESTLAKE.name), at(JUDKINS_PARK.name)];
+        let line_1_idx = |name| NORTH_TRAIN_INIT_IDX + stations::position(Route::Line1, name).unwrap() * 2;
+        let line_2_idx = |name| LINE_2_NORTH_TRAIN_INIT_IDX + stations::position(Route::Line2, name).unwrap() * 2;
+
+        let separate = strip("");
+        let frame = draw(&separate, trains());
+        assert_eq!(frame.len(), MAX_LEDS_FOR_STRIP * 2);
+        assert!(frame[line_2_idx(WESTLAKE.name)] == Led::ln_2_at_station());
+        assert!(frame[line_2_idx(JUDKINS_PARK.name)] == Led::ln_2_at_station());
+        assert!(frame[line_1_idx(WESTLAKE.name)] == LED_OFF);
+
+        let merged = strip("merge_line_2 = true\n");
+        let frame = draw(&merged, trains());
+        assert!(frame[line_1_idx(WESTLAKE.name)] == Led::ln_2_at_station());
+        assert!(frame[line_2_idx(JUDKINS_PARK.name)] == Led::ln_2_at_station());
+        let eastside_south = merged.layout.section(Route::Line2, Side::South).unwrap();
+        assert!(frame[eastside_south.staging_idx - 1] == Theme::default().buffers.mid);
+        assert!(frame[eastside_south.init_idx + PIXELS_FOR_EASTSIDE] == Theme::default().buffers.end);
+
+        assert_eq!(strip("routes = [1]\n").total_leds(), MAX_LEDS_FOR_STRIP);
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::{Duration, Instant};
+
+use log::{error, warn};
+
+use crate::{config::Config, display::Status, error::Error, tracker::TrainTracker, train::Train};
+
+/// How trains from the last successful fetch should be shown.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DataState {
+    /// Nothing has been fetched yet, so whatever is on the board stays.
+    Waiting,
+    /// Within the grace period, shown as usual.
+    Fresh,
+    /// Past the grace period, shown dimmed.
+    Stale,
+    /// Too old to show, or there's never been a successful fetch; the status says why.
+    NoData(Status),
+}
+
+/// The last trains fetched successfully, so the board can ride out a few failed fetches and move
+/// trains along between fetches.
+pub struct TrainCache {
+    last_good: Option<(Vec<Train>, Instant)>,
+    last_error: Option<Status>,
+    tracker: TrainTracker,
+    /// Whether to keep trains without a next stop, see `with_position_placement`.
+    places_by_position: bool,
+    grace_period: Duration,
+    no_data_after: Duration,
+}
+
+impl TrainCache {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            last_good: None,
+            last_error: None,
+            tracker: TrainTracker::default(),
+            places_by_position: false,
+            grace_period: config.grace_period(),
+            no_data_after: config.no_data_after(),
+        }
+    }
+
+    /// Keeps trains without a next stop for a display that can place them by position, see
+    /// `LinkBoardDisplay::places_by_position`. Otherwise they're skipped.
+    pub fn with_position_placement(mut self, places_by_position: bool) -> Self {
+        self.places_by_position = places_by_position;
+        self
+    }
+
+    /// Keeps the trains from a successful fetch, or what went wrong with a failed one.
+    pub fn update(&mut self, result: Result<Vec<Train>, Error>) {
+        match result {
+            Ok(trains) => self.store(&trains),
+            Err(e) => {
+                error!("failed to get trains: {e}");
+                self.last_error = Some(Status::from(&e));
+            },
+        }
+    }
+
+    /// Keeps `trains`, each with its history from the tracker.
+    pub fn store(&mut self, trains: &[Train]) {
+        let now = Instant::now();
+        let (trains, unplaceable): (Vec<Train>, Vec<Train>) = trains.iter()
+            .cloned()
+            .partition(|train| train.has_next_stop() || self.places_by_position);
+        if !unplaceable.is_empty() {
+            warn!("skipped {} trains without a next stop, the display can't place them by position", unplaceable.len());
+        }
+        self.tracker.update(&trains, now);
+        let trains = trains.into_iter()
+            .map(|train| {
+                let history = self.tracker.history(&train).cloned();
+                train.with_history(history)
+            })
+            .collect();
+        self.last_good = Some((trains, now));
+        self.last_error = None;
+    }
+
+    /// How to show the last good trains at `now`, and the trains moved along by the time since
+    /// they were fetched.
+    pub fn state_at(&self, now: Instant) -> (DataState, Vec<Train>) {
+        let status = self.last_error.unwrap_or(Status::FetchFailed);
+        let Some((trains, fetched_at)) = &self.last_good else {
+            let state = match self.last_error {
+                Some(status) => DataState::NoData(status),
+                None => DataState::Waiting,
+            };
+            return (state, vec![]);
+        };
+        let age = now.saturating_duration_since(*fetched_at);
+        // while fetches succeed the trains are as fresh as they can be, but a fetch that never
+        // finishes still ends in no data
+        let state = if age <= self.grace_period || self.last_error.is_none() && age <= self.no_data_after {
+            DataState::Fresh
+        } else if age <= self.no_data_after {
+            DataState::Stale
+        } else {
+            return (DataState::NoData(status), vec![]);
+        };
+        (state, trains.iter().map(|train| train.advanced(age)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{constants::Destination, display::Route};
+
+    use super::*;
+
+    #[test]
+    fn test_state_by_age() {
+        let config = Config::from_sources(Some("api_key = \"key\"\ngrace_period_secs = 60\nno_data_after_secs = 300\n"), |_| None).unwrap();
+        let mut cache = TrainCache::new(&config);
+        assert_eq!(cache.state_at(Instant::now()).0, DataState::Waiting);
+        cache.update(Err(Error::http_status_error(503, "")));
+        assert_eq!(cache.state_at(Instant::now()).0, DataState::NoData(Status::FetchFailed));
+
+        cache.store(&[Train::new("Westlake".to_string(), Route::Line1, Destination::LynnwoodCC, 90, 90)]);
+        cache.update(Err(Error::http_status_error(401, "")));
+        let now = Instant::now();
+        let state = |secs| {
+            let (state, trains) = cache.state_at(now + Duration::from_secs(secs));
+            (state, trains.first().map(Train::next_stop_time_offset))
+        };
+        assert_eq!(state(0), (DataState::Fresh, Some(90)));
+        assert_eq!(state(30), (DataState::Fresh, Some(60)));
+        assert_eq!(state(61), (DataState::Stale, Some(29)));
+        assert_eq!(state(301), (DataState::NoData(Status::ApiKeyRejected), None));
+    }
+
+    #[test]
+    fn test_trains_without_next_stop() {
+        let config = Config::from_sources(Some("api_key = \"key\"\n"), |_| None).unwrap();
+        let trains = [
+            Train::new("Westlake".to_string(), Route::Line1, Destination::LynnwoodCC, 90, 90),
+            Train::new(String::new(), Route::Line1, Destination::LynnwoodCC, 1, 1).with_position(Some((47.6104, -122.3363))),
+        ];
+        let mut cache = TrainCache::new(&config);
+        cache.store(&trains);
+        assert_eq!(cache.state_at(Instant::now()).1.len(), 1);
+
+        let mut cache = TrainCache::new(&config).with_position_placement(true);
+        cache.store(&trains);
+        assert_eq!(cache.state_at(Instant::now()).1.len(), 2);
+    }
+}
@@ -1,6 +1,9 @@
 use core::fmt;
+use std::time::Duration;
 
 use log::SetLoggerError;
+
+use crate::layout::ValidationReport;
 use tokio::io;
 
 pub struct Error {
@@ -11,25 +14,84 @@ struct ErrorImpl {
     kind: Kind
 }
 
+#[allow(clippy::enum_variant_names)]
 enum Kind {
+    ApiError(ApiErr),
     #[cfg(feature = "cli")]
     ClientError(reqwest::Error),
+    ConfigError(ConfigErr),
+    /// A write to the LEDs failed.
+    DisplayError(String),
+    #[cfg(feature = "cli")]
+    GtfsError(GtfsErr),
+    HttpStatus { status: u16, body: String },
     IoError(io::Error),
+    LayoutError(LayoutErr),
     JsonParseError(serde_json::Error),
     LoggerError(SetLoggerError),
+    ProtobufError(prost::DecodeError),
+    RateLimited { body: String, retry_after: Option<Duration> },
     TripParseError(TripParseErr),
+    UnknownStop(String),
+    UnknownTrip(String),
 }
 
 #[derive(Debug)]
 pub enum TripParseErr {
     Id,
+    Status,
     NextStop,
+    NextStopTimeOffset,
     ClosestStopTimeOffset,
     NotInProgress,
     BeyondLastStop,
 }
 
+/// Problems reported in the OneBusAway response envelope.
+#[derive(Debug)]
+pub enum ApiErr {
+    /// `code` other than 200, with the response's `text`
+    Code { code: i64, text: String },
+    /// `data.limitExceeded`, so the trip list is incomplete
+    LimitExceeded,
+    /// a 200 response without `data`
+    MissingData,
+}
+
+#[derive(Debug)]
+pub enum ConfigErr {
+    Missing(&'static str),
+    Invalid { key: &'static str, value: String },
+    Toml(toml::de::Error),
+}
+
+#[cfg(feature = "cli")]
+#[derive(Debug)]
+pub enum GtfsErr {
+    Zip(zip::result::ZipError),
+    Csv { file: &'static str, err: csv::Error },
+    MissingRoute(String),
+    NoTrips(String),
+}
+
+#[derive(Debug)]
+pub enum LayoutErr {
+    Toml(toml::de::Error),
+    Invalid(ValidationReport),
+}
+
+/// Longest response body kept in an error message.
+const MAX_BODY_SNIPPET: usize = 200;
+
 impl Error {
+    pub fn api_error(api_err: ApiErr) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::ApiError(api_err),
+            })
+        }
+    }
+
     #[cfg(feature = "cli")]
     pub fn client_error(req_err: reqwest::Error) -> Self {
         Self {
@@ -39,6 +101,39 @@ impl Error {
         }
     }
 
+    pub fn config_error(config_err: ConfigErr) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::ConfigError(config_err),
+            })
+        }
+    }
+
+    pub fn display_error(message: String) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::DisplayError(message),
+            })
+        }
+    }
+
+    #[cfg(feature = "cli")]
+    pub fn gtfs_error(gtfs_err: GtfsErr) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::GtfsError(gtfs_err),
+            })
+        }
+    }
+
+    pub fn http_status_error(status: u16, body: &str) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::HttpStatus { status, body: snippet(body) },
+            })
+        }
+    }
+
     pub fn io_error(io_err: io::Error) -> Self {
         Self {
             err: Box::new(ErrorImpl {
@@ -47,6 +142,14 @@ impl Error {
         }
     }
 
+    pub fn layout_error(layout_err: LayoutErr) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::LayoutError(layout_err),
+            })
+        }
+    }
+
     pub fn json_error(serde_err: serde_json::Error) -> Self {
         Self {
             err: Box::new(ErrorImpl {
@@ -63,6 +166,22 @@ impl Error {
         }
     }
 
+    pub fn protobuf_error(decode_err: prost::DecodeError) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::ProtobufError(decode_err),
+            })
+        }
+    }
+
+    pub fn rate_limited_error(body: &str, retry_after: Option<Duration>) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::RateLimited { body: snippet(body), retry_after },
+            })
+        }
+    }
+
     pub fn trip_parse_error(trip_err: TripParseErr) -> Self {
         Self {
             err: Box::new(ErrorImpl {
@@ -71,14 +190,73 @@ impl Error {
         }
     }
 
+    pub fn unknown_stop_error(stop: String) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::UnknownStop(stop),
+            })
+        }
+    }
+
+    pub fn unknown_trip_error(trip_id: String) -> Self {
+        Self {
+            err: Box::new(ErrorImpl {
+                kind: Kind::UnknownTrip(trip_id),
+            })
+        }
+    }
+
     pub fn is_not_in_progress_err(&self) -> bool {
-        match self.err.kind {
-            Kind::TripParseError(TripParseErr::NotInProgress) => true,
-            _ => false
+        matches!(self.err.kind, Kind::TripParseError(TripParseErr::NotInProgress))
+    }
+
+    /// Whether the same request might succeed if it's tried again: timeouts, connection problems,
+    /// server errors and rate limiting.
+    pub fn is_retryable(&self) -> bool {
+        match &self.err.kind {
+            #[cfg(feature = "cli")]
+            Kind::ClientError(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            Kind::HttpStatus { status, .. } => *status == 408 || *status >= 500,
+            Kind::IoError(e) => !matches!(e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied | io::ErrorKind::InvalidInput
+                | io::ErrorKind::InvalidData | io::ErrorKind::Unsupported),
+            Kind::RateLimited { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the server refused our API key, either with an HTTP status or in the OneBusAway
+    /// response.
+    pub fn is_api_key_rejected(&self) -> bool {
+        match &self.err.kind {
+            Kind::HttpStatus { status, .. } => *status == 401 || *status == 403,
+            Kind::ApiError(ApiErr::Code { code, .. }) => *code == 401 || *code == 403,
+            _ => false,
+        }
+    }
+
+    /// Whether a response arrived but couldn't be understood.
+    pub fn is_parse_error(&self) -> bool {
+        matches!(self.err.kind,
+            Kind::JsonParseError(_) | Kind::ProtobufError(_) | Kind::TripParseError(_) | Kind::ApiError(ApiErr::MissingData))
+    }
+
+    /// How long the server asked us to wait before trying again, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match &self.err.kind {
+            Kind::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
         }
     }
 }
 
+fn snippet(body: &str) -> String {
+    let body = body.trim();
+    match body.char_indices().nth(MAX_BODY_SNIPPET) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.to_string(),
+    }
+}
 
 #[cfg(feature = "cli")]
 impl From<reqwest::Error> for Error {
@@ -99,6 +277,12 @@ impl From<SetLoggerError> for Error {
     }
 }
 
+impl From<prost::DecodeError> for Error {
+    fn from(value: prost::DecodeError) -> Self {
+        Error::protobuf_error(value)
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(value: io::Error) -> Self {
         Error::io_error(value)
@@ -114,12 +298,65 @@ impl fmt::Display for Error {
 impl fmt::Display for ErrorImpl {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.kind {
+            Kind::ApiError(e) => write!(f, "OneBusAway error: {e}"),
             #[cfg(feature = "cli")]
             Kind::ClientError(e) => write!(f, "error retrieving data: {e}"),
+            Kind::ConfigError(e) => write!(f, "invalid configuration: {e}"),
+            Kind::DisplayError(e) => write!(f, "error writing LEDs: {e}"),
+            #[cfg(feature = "cli")]
+            Kind::GtfsError(e) => write!(f, "error reading GTFS feed: {e}"),
+            Kind::HttpStatus { status, body } => write!(f, "HTTP status {status}: {body}"),
             Kind::IoError(e) => write!(f, "tokio::io error: {e}"),
+            Kind::LayoutError(e) => write!(f, "invalid layout: {e}"),
             Kind::JsonParseError(e) => write!(f, "error parsing JSON: {e}"),
             Kind::LoggerError(e) => write!(f, "logging error: {e}"),
+            Kind::ProtobufError(e) => write!(f, "error decoding GTFS-Realtime feed: {e}"),
+            Kind::RateLimited { body, retry_after: Some(retry_after) } => write!(f, "rate limited, retry after {}s: {body}", retry_after.as_secs()),
+            Kind::RateLimited { body, retry_after: None } => write!(f, "rate limited: {body}"),
             Kind::TripParseError(trip_err) => write!(f, "failed to find {trip_err:?} for trip"),
+            Kind::UnknownStop(stop) => write!(f, "unknown stop {stop}"),
+            Kind::UnknownTrip(trip_id) => write!(f, "unknown trip {trip_id} (no direction for it)"),
+        }
+    }
+}
+
+impl fmt::Display for ApiErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiErr::Code { code, text } => write!(f, "code {code}: {text}"),
+            ApiErr::LimitExceeded => write!(f, "limit exceeded, trips are missing from the response"),
+            ApiErr::MissingData => write!(f, "no data in response"),
+        }
+    }
+}
+
+impl fmt::Display for ConfigErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigErr::Missing(key) => write!(f, "missing value for `{key}`"),
+            ConfigErr::Invalid { key, value } => write!(f, "invalid value {value:?} for `{key}`"),
+            ConfigErr::Toml(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl fmt::Display for GtfsErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GtfsErr::Zip(e) => write!(f, "{e}"),
+            GtfsErr::Csv { file, err } => write!(f, "{file}: {err}"),
+            GtfsErr::MissingRoute(route_id) => write!(f, "no route {route_id} in routes.txt"),
+            GtfsErr::NoTrips(route_id) => write!(f, "no trips for route {route_id}"),
+        }
+    }
+}
+
+impl fmt::Display for LayoutErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutErr::Toml(e) => write!(f, "{e}"),
+            LayoutErr::Invalid(report) => write!(f, "{} error(s), first: {}", report.error_count(), report.errors().next().map(|i| i.message()).unwrap_or_default()),
         }
     }
 }
@@ -0,0 +1,290 @@
+//! Trains from GTFS-Realtime `VehiclePositions` and `TripUpdates` feeds, as an alternative to
+//! OneBusAway.
+
+use std::{collections::{HashMap, HashSet}, fs, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use log::{debug, info, warn};
+use prost::Message;
+
+use crate::{
+    config::Config,
+    constants::Destination,
+    data_parser::dir_id_to_destination,
+    data_retriever::TrainSource,
+    display::Route,
+    error::{Error, TripParseErr},
+    retry::RetryPolicy,
+    stations,
+    train::{Train, TrainIds}
+};
+use proto::{FeedEntity, FeedMessage, TripDescriptor, VehicleDescriptor, VehiclePosition, VehicleStopStatus};
+
+mod proto;
+
+pub fn get_gtfs_rt_retriever(config: &Config) -> GtfsRtRetriever {
+    GtfsRtRetriever::new(config)
+}
+
+/// Reads every feed in `Config::gtfs_rt_feeds`, from a URL or a file.
+pub struct GtfsRtRetriever {
+    config: Config,
+}
+
+impl GtfsRtRetriever {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    async fn fetch(&self, feed: &str) -> Result<Vec<u8>, Error> {
+        if feed.starts_with("http://") || feed.starts_with("https://") {
+            fetch_url(feed, &RetryPolicy::new(&self.config)).await
+        } else {
+            Ok(fs::read(feed)?)
+        }
+    }
+}
+
+impl TrainSource for GtfsRtRetriever {
+    async fn get_all_trains(&self) -> Result<Vec<Train>, Error> {
+        let mut feeds = vec![];
+        for feed in self.config.gtfs_rt_feeds() {
+            let bytes = self.fetch(feed).await?;
+            debug!("retrieved {} bytes from {}", bytes.len(), feed);
+            feeds.push(FeedMessage::decode(bytes.as_slice())?);
+        }
+
+        let (trains, skipped) = parse_feeds(&feeds, &self.config);
+        if skipped > 0 {
+            warn!("skipped {} of {} trains", skipped, trains.len() + skipped);
+        }
+        Ok(trains)
+    }
+}
+
+#[cfg(feature = "cli")]
+async fn fetch_url(url: &str, policy: &RetryPolicy) -> Result<Vec<u8>, Error> {
+    use crate::{data_retriever::check_status, retry::retry};
+    use reqwest::header::RETRY_AFTER;
+
+    let client = reqwest::Client::builder().timeout(policy.timeout).build()?;
+    retry(policy, || async {
+        let response = client.get(url).send().await?;
+        match response.status().as_u16() {
+            200..=299 => Ok(response.bytes().await?.to_vec()),
+            status => {
+                let retry_after = response.headers().get(RETRY_AFTER).and_then(|v| v.to_str().ok()).map(str::to_string);
+                check_status(status, retry_after.as_deref(), response.text().await?).map(String::into_bytes)
+            },
+        }
+    }, tokio::time::sleep).await
+}
+
+#[cfg(not(feature = "cli"))]
+async fn fetch_url(url: &str, _policy: &RetryPolicy) -> Result<Vec<u8>, Error> {
+    Err(Error::io_error(std::io::Error::new(std::io::ErrorKind::Unsupported, format!("can't fetch {url} without the `cli` feature"))))
+}
+
+/// A train's upcoming stop, and when it will get there.
+struct NextStop<'a> {
+    stop_id: &'a str,
+    /// POSIX time, if the feed predicts one
+    time: Option<i64>,
+}
+
+/// Returns the trains on the configured routes and the number of trips that were skipped because
+/// they couldn't be placed on the board. Trips with a vehicle position are placed at the vehicle's
+/// stop; trips that only have a trip update are placed before their next predicted stop.
+fn parse_feeds(feeds: &[FeedMessage], config: &Config) -> (Vec<Train>, usize) {
+    let mut trains = vec![];
+    let mut skipped = 0;
+
+    // entities with the time of the feed they came from, since predictions are relative to it
+    let entities: Vec<(i64, &FeedEntity)> = feeds.iter()
+        .flat_map(|feed| {
+            let now = feed.header.timestamp.and_then(|t| i64::try_from(t).ok()).unwrap_or_else(unix_now);
+            feed.entity.iter().filter(|e| !e.is_deleted()).map(move |e| (now, e))
+        })
+        .collect();
+
+    let mut predictions: HashMap<&str, Vec<NextStop>> = HashMap::new();
+    for update in entities.iter().filter_map(|(_, e)| e.trip_update.as_ref()) {
+        let Some(trip_id) = update.trip.trip_id.as_deref() else {
+            continue;
+        };
+        let stops = update.stop_time_update.iter().filter_map(|stu| Some(NextStop {
+            stop_id: stu.stop_id.as_deref()?,
+            time: stu.arrival.as_ref().or(stu.departure.as_ref()).and_then(|e| e.time),
+        }));
+        predictions.insert(trip_id, stops.collect());
+    }
+
+    // trips placed by their vehicle position, so their trip updates aren't made into trains as well
+    let mut placed: HashSet<&str> = HashSet::new();
+    for (now, vehicle) in entities.iter().filter_map(|(now, e)| Some((*now, e.vehicle.as_ref()?))) {
+        let Some(trip) = &vehicle.trip else {
+            continue;
+        };
+        let Some(route) = configured_route(trip, config) else {
+            continue;
+        };
+        let trip_id = trip.trip_id.as_deref().unwrap_or_default();
+
+        let stopped = vehicle.current_status() == VehicleStopStatus::StoppedAt;
+        let result = vehicle.stop_id.as_deref()
+            .ok_or_else(|| Error::trip_parse_error(TripParseErr::NextStop))
+            .and_then(|stop_id| {
+                let time = predictions.get(trip_id)
+                    .and_then(|stops| stops.iter().find(|s| s.stop_id == stop_id))
+                    .and_then(|s| s.time);
+                to_train(trip, vehicle.vehicle.as_ref(), route, &NextStop { stop_id, time }, stopped, now)
+            })
+            .map(|train| train.with_age(vehicle_age(vehicle, now)).with_position(vehicle_position(vehicle)));
+        match result {
+            Ok(train) => {
+                if let Some(trip_id) = trip.trip_id.as_deref() {
+                    placed.insert(trip_id);
+                }
+                trains.push(train);
+            },
+            // its trip update may still place it
+            Err(e) if predictions.contains_key(trip_id) => info!("placing trip {} by its trip update: {e}", trip_id),
+            Err(e) => {
+                warn!("skipping trip {} on route {:?}: {e}", trip_id, route);
+                skipped += 1;
+            },
+        }
+    }
+
+    for (now, update) in entities.iter().filter_map(|(now, e)| Some((*now, e.trip_update.as_ref()?))) {
+        let trip = &update.trip;
+        let Some(route) = configured_route(trip, config) else {
+            continue;
+        };
+        let trip_id = trip.trip_id.as_deref().unwrap_or_default();
+        if placed.contains(trip_id) {
+            continue;
+        }
+
+        let next_stop = predictions.get(trip_id)
+            .and_then(|stops| stops.iter().find(|s| s.time.is_some_and(|t| t >= now)));
+        let result = next_stop
+            .ok_or_else(|| Error::trip_parse_error(TripParseErr::NextStop))
+            .and_then(|next_stop| to_train(trip, update.vehicle.as_ref(), route, next_stop, false, now));
+        match result {
+            Ok(train) => trains.push(train),
+            Err(e) => {
+                warn!("skipping trip {} on route {:?}: {e}", trip_id, route);
+                skipped += 1;
+            },
+        }
+    }
+
+    info!("parsed {} trains from {} GTFS-Realtime feed(s)", trains.len(), feeds.len());
+    (trains, skipped)
+}
+
+/// How old the vehicle's position was at the feed's time, if it has a timestamp.
+fn vehicle_age(vehicle: &VehiclePosition, now: i64) -> Option<Duration> {
+    let timestamp = i64::try_from(vehicle.timestamp?).ok()?;
+    Some(Duration::from_secs(now.saturating_sub(timestamp).max(0) as u64))
+}
+
+fn vehicle_position(vehicle: &VehiclePosition) -> Option<(f64, f64)> {
+    vehicle.position.as_ref().map(|p| (f64::from(p.latitude), f64::from(p.longitude)))
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+fn to_train(
+    trip: &TripDescriptor,
+    vehicle: Option<&VehicleDescriptor>,
+    route: Route,
+    next_stop: &NextStop,
+    stopped: bool,
+    now: i64
+) -> Result<Train, Error> {
+    let Some(station) = stations::for_stop_id(next_stop.stop_id).filter(|s| stations::contains(route, s.name)) else {
+        return Err(Error::unknown_stop_error(next_stop.stop_id.to_string()));
+    };
+    let Some(destination) = destination(trip, route) else {
+        return Err(Error::unknown_trip_error(trip.trip_id.clone().unwrap_or_default()));
+    };
+
+    // a train is only drawn at a station when both offsets are 0, so a train that hasn't stopped
+    // yet is always at least a second away
+    let offset = if stopped {
+        0
+    } else {
+        next_stop.time.map_or(1, |t| (t - now).max(1))
+    };
+    let ids = TrainIds {
+        trip_id: trip.trip_id.clone().unwrap_or_default(),
+        vehicle_id: vehicle.and_then(|v| v.id.clone()),
+        ..Default::default()
+    };
+    Ok(Train::new(station.name.to_string(), route, destination, offset, offset).with_ids(ids))
+}
+
+fn destination(trip: &TripDescriptor, route: Route) -> Option<Destination> {
+    let direction_id = trip.direction_id?.to_string();
+    dir_id_to_destination(Some(&direction_id), route)
+}
+
+/// The configured route the trip is on, matching route IDs with or without the agency prefix.
+fn configured_route(trip: &TripDescriptor, config: &Config) -> Option<Route> {
+    let route_id = trip.route_id.as_deref()?;
+    config.routes().iter().copied().find(|&route| {
+        let oba_id = config.route_id(route);
+        oba_id == route_id || oba_id.split_once('_').is_some_and(|(_, unprefixed)| unprefixed == route_id)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VEHICLE_POSITIONS: &[u8] = include_bytes!("../fixtures/gtfs_rt/vehicle_positions.pb");
+    const TRIP_UPDATES: &[u8] = include_bytes!("../fixtures/gtfs_rt/trip_updates.pb");
+
+    fn config() -> Config {
+        Config::from_sources(Some("gtfs_rt_feeds = [\"feed.pb\"]\n"), |_| None).unwrap()
+    }
+
+    #[test]
+    fn test_parse_fixtures() {
+        let feeds = [
+            FeedMessage::decode(VEHICLE_POSITIONS).unwrap(),
+            FeedMessage::decode(TRIP_UPDATES).unwrap(),
+        ];
+        let (trains, skipped) = parse_feeds(&feeds, &config());
+
+        // the bus and the vehicle without a trip are ignored, the detour stop is skipped, and the
+        // vehicle without a stop is placed by its trip update
+        assert_eq!(skipped, 1);
+        let summary: Vec<(String, Route, Destination, bool)> = trains.iter()
+            .map(|t| (t.next_stop_name.clone(), t.route(), t.destination(), t.at_station()))
+            .collect();
+        assert_eq!(summary, [
+            ("SeaTac/Airport".to_string(), Route::Line1, Destination::FederalWayDT, true),
+            ("Westlake".to_string(), Route::Line1, Destination::LynnwoodCC, false),
+            ("Wilburton".to_string(), Route::Line2, Destination::RedmondDT, false),
+            ("Northgate".to_string(), Route::Line1, Destination::FederalWayDT, false),
+            ("Westlake".to_string(), Route::Line1, Destination::LynnwoodCC, false),
+        ]);
+        assert_eq!(trains[1].next_stop_time_offset(), 90);
+        assert_eq!(trains[4].next_stop_time_offset(), 60);
+    }
+
+    #[test]
+    fn test_only_configured_routes() {
+        let config = Config::from_sources(Some("gtfs_rt_feeds = [\"feed.pb\"]\nroutes = [2]\n"), |_| None).unwrap();
+        let (trains, skipped) = parse_feeds(&[FeedMessage::decode(VEHICLE_POSITIONS).unwrap()], &config);
+        assert_eq!((trains.len(), skipped), (1, 0));
+        assert_eq!(trains[0].route(), Route::Line2);
+    }
+}
+
@@ -0,0 +1,390 @@
+//! Station order from a static GTFS feed, for regenerating the station registry and a starting map
+//! layout when stations open.
+//!
+//! Each configured route's station order is taken from its longest Lynnwood-bound trip, with
+//! platforms collapsed into their parent station. Stations already in the registry keep their names
+//! and constant names, so layout files keep working.
+
+use std::{collections::{HashMap, HashSet}, fmt::Write, fs::File, io::{Read, Seek}, path::Path};
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    constants::Destination,
+    data_parser::dir_id_to_destination,
+    display::Route,
+    error::{Error, GtfsErr},
+    layout::MapLayout,
+    stations::{self, Station}
+};
+
+const REGISTRY_SOURCE: &str = include_str!("stations/registry.rs");
+
+/// A station as it will be written to the registry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportedStation {
+    /// Name of the registry constant.
+    pub ident: String,
+    /// Stop ID with the agency prefix OneBusAway uses, e.g. `40_C03`.
+    pub stop_id: String,
+    pub name: String,
+    pub platform_ids: Vec<String>,
+    /// Whether the station was already in the registry.
+    pub existing: bool,
+}
+
+/// Stations of both lines in the order Lynnwood-bound trains visit them, like `stations::LINE_1`
+/// and `stations::LINE_2`.
+#[derive(Debug)]
+pub struct ImportedLines {
+    pub line_1: Vec<ImportedStation>,
+    pub line_2: Vec<ImportedStation>,
+}
+
+#[derive(Deserialize)]
+struct StopRow {
+    stop_id: String,
+    stop_name: String,
+    #[serde(default)]
+    parent_station: String,
+    /// 0 or empty for platforms, 1 for stations; entrances and the like are ignored.
+    #[serde(default)]
+    location_type: String,
+}
+
+#[derive(Deserialize)]
+struct TripRow {
+    route_id: String,
+    trip_id: String,
+    #[serde(default)]
+    direction_id: String,
+}
+
+#[derive(Deserialize)]
+struct StopTimeRow {
+    trip_id: String,
+    stop_id: String,
+    stop_sequence: u32,
+}
+
+#[derive(Deserialize)]
+struct RouteRow {
+    route_id: String,
+}
+
+/// Reads the configured routes from a GTFS zip file.
+pub fn read_zip(path: &Path, config: &Config) -> Result<ImportedLines, Error> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(|e| Error::gtfs_error(GtfsErr::Zip(e)))?;
+    let routes = read_file(&mut archive, "routes.txt")?;
+    let trips = read_file(&mut archive, "trips.txt")?;
+    let stop_times = read_file(&mut archive, "stop_times.txt")?;
+    let stops = read_file(&mut archive, "stops.txt")?;
+    read_tables(stops.as_slice(), trips.as_slice(), stop_times.as_slice(), routes.as_slice(), config)
+}
+
+fn read_file(archive: &mut zip::ZipArchive<impl Read + Seek>, name: &'static str) -> Result<Vec<u8>, Error> {
+    let mut file = archive.by_name(name).map_err(|e| Error::gtfs_error(GtfsErr::Zip(e)))?;
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads the configured routes from the contents of `stops.txt`, `trips.txt`, `stop_times.txt`
+/// and `routes.txt`.
+pub fn read_tables(stops: impl Read, trips: impl Read, stop_times: impl Read, routes: impl Read, config: &Config) -> Result<ImportedLines, Error> {
+    let route_ids: Vec<(Route, String)> = [Route::Line1, Route::Line2].into_iter()
+        .map(|route| (route, unprefixed(config.route_id(route)).to_string()))
+        .collect();
+    let prefix = agency_prefix(config.route_id(Route::Line1));
+
+    let known_routes: HashSet<String> = rows::<RouteRow>(routes, "routes.txt")?
+        .into_iter()
+        .map(|r| r.route_id)
+        .collect();
+    if let Some((_, missing)) = route_ids.iter().find(|(_, id)| !known_routes.contains(id)) {
+        return Err(Error::gtfs_error(GtfsErr::MissingRoute(missing.clone())));
+    }
+
+    // (route, destination) of every trip on the configured routes
+    let mut trip_routes = HashMap::new();
+    for trip in rows::<TripRow>(trips, "trips.txt")? {
+        if let Some((route, _)) = route_ids.iter().find(|(_, id)| *id == trip.route_id) {
+            if let Some(destination) = dir_id_to_destination(Some(&trip.direction_id), *route) {
+                trip_routes.insert(trip.trip_id, (*route, destination));
+            }
+        }
+    }
+
+    let mut trip_stops: HashMap<String, Vec<(u32, String)>> = HashMap::new();
+    let mut reader = csv::Reader::from_reader(stop_times);
+    for row in reader.deserialize::<StopTimeRow>() {
+        let row = row.map_err(|err| Error::gtfs_error(GtfsErr::Csv { file: "stop_times.txt", err }))?;
+        if trip_routes.contains_key(&row.trip_id) {
+            trip_stops.entry(row.trip_id).or_default().push((row.stop_sequence, row.stop_id));
+        }
+    }
+
+    let stops: HashMap<String, StopRow> = rows::<StopRow>(stops, "stops.txt")?
+        .into_iter()
+        .map(|s| (s.stop_id.clone(), s))
+        .collect();
+
+    let mut lines = vec![];
+    for (route, route_id) in &route_ids {
+        let towards_lynnwood = longest_trip(&trip_stops, &trip_routes, &stops, *route, Destination::LynnwoodCC);
+        let mut away = longest_trip(&trip_stops, &trip_routes, &stops, *route, dir_id_to_destination(Some("0"), *route).unwrap_or_default());
+        away.reverse();
+
+        let order = match (towards_lynnwood.is_empty(), away.is_empty()) {
+            (true, true) => return Err(Error::gtfs_error(GtfsErr::NoTrips(route_id.clone()))),
+            (true, false) => away,
+            (false, true) => towards_lynnwood,
+            (false, false) => {
+                if towards_lynnwood != away {
+                    warn!("{route:?} stops differ by direction, using the Lynnwood-bound order");
+                }
+                towards_lynnwood
+            },
+        };
+        lines.push(order.iter().map(|id| to_station(id, &stops, &prefix)).collect());
+    }
+
+    let line_2 = lines.pop().unwrap_or_default();
+    let line_1 = lines.pop().unwrap_or_default();
+    Ok(ImportedLines { line_1, line_2 })
+}
+
+fn rows<T: serde::de::DeserializeOwned>(reader: impl Read, file: &'static str) -> Result<Vec<T>, Error> {
+    csv::Reader::from_reader(reader)
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .map_err(|err| Error::gtfs_error(GtfsErr::Csv { file, err }))
+}
+
+/// Parent station IDs of the trip with the most stops on `route` towards `destination`.
+fn longest_trip(
+    trip_stops: &HashMap<String, Vec<(u32, String)>>,
+    trip_routes: &HashMap<String, (Route, Destination)>,
+    stops: &HashMap<String, StopRow>,
+    route: Route,
+    destination: Destination
+) -> Vec<String> {
+    let mut trip_ids: Vec<&String> = trip_routes.iter()
+        .filter(|(_, &route_and_dest)| route_and_dest == (route, destination))
+        .map(|(trip_id, _)| trip_id)
+        .collect();
+    // sorted so the same feed always gives the same order
+    trip_ids.sort();
+
+    let mut longest: Vec<String> = vec![];
+    for trip_id in trip_ids {
+        let Some(trip) = trip_stops.get(trip_id) else {
+            continue;
+        };
+        let mut trip = trip.clone();
+        trip.sort();
+        let mut stations: Vec<String> = trip.into_iter().map(|(_, stop_id)| parent_of(&stop_id, stops)).collect();
+        stations.dedup();
+        if stations.len() > longest.len() {
+            longest = stations;
+        }
+    }
+    longest
+}
+
+fn parent_of(stop_id: &str, stops: &HashMap<String, StopRow>) -> String {
+    stops.get(stop_id)
+        .map(|s| &s.parent_station)
+        .filter(|p| !p.is_empty())
+        .cloned()
+        .unwrap_or_else(|| stop_id.to_string())
+}
+
+fn to_station(stop_id: &str, stops: &HashMap<String, StopRow>, prefix: &str) -> ImportedStation {
+    let gtfs_name = stops.get(stop_id).map_or(stop_id, |s| s.stop_name.as_str());
+    let gtfs_name = gtfs_name.strip_suffix(" Station").unwrap_or(gtfs_name).trim();
+
+    let mut platform_ids: Vec<String> = stops.values()
+        .filter(|s| s.parent_station == stop_id && matches!(s.location_type.as_str(), "" | "0"))
+        .map(|s| format!("{prefix}{}", s.stop_id))
+        .collect();
+    platform_ids.sort();
+
+    let existing = stations::for_stop_id(stop_id)
+        .or_else(|| registry().find(|s| s.stop_id.is_none() && s.name == gtfs_name));
+    ImportedStation {
+        ident: existing.and_then(ident_of).map_or_else(|| to_ident(gtfs_name), str::to_string),
+        stop_id: format!("{prefix}{stop_id}"),
+        name: existing.map_or(gtfs_name, |s| s.name).to_string(),
+        platform_ids,
+        existing: existing.is_some(),
+    }
+}
+
+/// Stations in the current registry.
+fn registry() -> impl Iterator<Item = &'static Station> {
+    stations::line(Route::Line1).iter().chain(stations::line(Route::Line2))
+}
+
+/// The registry constant for a station, so regenerating the registry doesn't rename it.
+fn ident_of(station: &Station) -> Option<&'static str> {
+    let name = format!("{:?}", station.name);
+    REGISTRY_SOURCE.lines()
+        .filter_map(|line| line.strip_prefix("pub const "))
+        .find(|line| line.contains(": Station = ") && line.contains(&name))
+        .and_then(|line| line.split_once(':'))
+        .map(|(ident, _)| ident)
+}
+
+/// `Capitol Hill` -> `CAPITOL_HILL`
+fn to_ident(name: &str) -> String {
+    let ident = name.to_uppercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("STN_{ident}")
+    } else {
+        ident
+    }
+}
+
+fn unprefixed(id: &str) -> &str {
+    id.split_once('_').map_or(id, |(_, unprefixed)| unprefixed)
+}
+
+/// `40_` for `40_100479`
+fn agency_prefix(id: &str) -> String {
+    id.split_once('_').map_or(String::new(), |(agency, _)| format!("{agency}_"))
+}
+
+impl ImportedLines {
+    fn line(&self, route: Route) -> &[ImportedStation] {
+        match route {
+            Route::Line1 => &self.line_1,
+            Route::Line2 => &self.line_2,
+        }
+    }
+
+    fn names(&self, route: Route) -> Vec<&str> {
+        self.line(route).iter().map(|s| s.name.as_str()).collect()
+    }
+
+    /// Stations that aren't in the current registry.
+    pub fn added(&self) -> impl Iterator<Item = &ImportedStation> {
+        let mut seen = HashSet::new();
+        self.line_1.iter().chain(&self.line_2).filter(move |s| !s.existing && seen.insert(&s.name))
+    }
+
+    /// Stations in the current registry that aren't in the feed.
+    pub fn removed(&self) -> Vec<&'static str> {
+        // stations on both lines are in the registry twice
+        let mut seen = HashSet::new();
+        registry()
+            .map(|s| s.name)
+            .filter(|name| !self.line_1.iter().chain(&self.line_2).any(|s| s.name == *name))
+            .filter(|name| seen.insert(*name))
+            .collect()
+    }
+
+    /// A starting map layout with `leds_between` LEDs between stops, see `MapLayout::skeleton`.
+    pub fn layout(&self, leds_between: usize) -> MapLayout {
+        MapLayout::skeleton(&self.names(Route::Line1), &self.names(Route::Line2), leds_between)
+    }
+
+    /// Source for `src/stations/registry.rs`.
+    pub fn registry_source(&self) -> String {
+        let mut out = String::from("\
+//! Station data. `link-board import-gtfs` regenerates this file from a static GTFS feed, keeping
+//! the names of stations that are already here; stations without a stop ID were added by hand.
+
+use super::{station, Station};
+
+");
+        // the 1 Line, then the 2 Line branch from where it splits off
+        let branch = self.line_2.iter().rev().filter(|s| !self.line_1.iter().any(|l| l.ident == s.ident));
+        for station in self.line_1.iter().chain(branch) {
+            let platform_ids: Vec<String> = station.platform_ids.iter().map(|id| format!("{id:?}")).collect();
+            let _ = writeln!(out, "pub const {}: Station = station({:?}, {:?}, &[{}]);",
+                station.ident, station.stop_id, station.name, platform_ids.join(", "));
+        }
+
+        for (route, number) in [(Route::Line1, 1), (Route::Line2, 2)] {
+            let line = self.line(route);
+            let _ = writeln!(out, "\n/// {number} Line stations in the order Lynnwood-bound trains visit them.");
+            let _ = writeln!(out, "pub const LINE_{number}: [Station; {}] = [", line.len());
+            for station in line {
+                let _ = writeln!(out, "    {},", station.ident);
+            }
+            out.push_str("];\n");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTES: &str = "route_id,route_short_name\n100479,1 Line\n2LINE,2 Line\n";
+    const TRIPS: &str = "route_id,service_id,trip_id,direction_id
+100479,wk,n1,1
+100479,wk,s1,0
+2LINE,wk,e1,1
+";
+    const STOP_TIMES: &str = "trip_id,arrival_time,departure_time,stop_id,stop_sequence
+n1,08:00:00,08:00:00,99913,1
+n1,08:04:00,08:04:00,NEW-T1,2
+n1,08:10:00,08:10:00,N23-T1,3
+s1,09:00:00,09:00:00,N23-T2,10
+s1,09:06:00,09:06:00,NEW-T2,20
+s1,09:10:00,09:10:00,99914,30
+e1,08:00:00,08:00:00,E27-T1,1
+e1,08:10:00,08:10:00,NEW-T1,2
+e1,08:20:00,08:20:00,N23-T1,3
+";
+    const STOPS: &str = "stop_id,stop_name,parent_station,location_type
+S01,Angle Lake Station,,1
+99913,Angle Lake Station,S01,0
+99914,Angle Lake Station,S01,0
+NEW,Fancy New Station,,1
+NEW-T1,Fancy New Station,NEW,0
+NEW-T2,Fancy New Station,NEW,0
+NEW-E,Fancy New Station Entrance,NEW,2
+N23,Lynnwood City Center Station,,1
+N23-T1,Lynnwood City Center Station,N23,0
+N23-T2,Lynnwood City Center Station,N23,0
+E27,Redmond Technology Station,,1
+E27-T1,Redmond Technology Station,E27,0
+";
+
+    #[test]
+    fn test_read_tables() {
+        let config = Config::from_sources(Some("gtfs_rt_feeds = [\"feed.pb\"]\n"), |_| None).unwrap();
+        let lines = read_tables(STOPS.as_bytes(), TRIPS.as_bytes(), STOP_TIMES.as_bytes(), ROUTES.as_bytes(), &config).unwrap();
+
+        assert_eq!(lines.names(Route::Line1), ["Angle Lake", "Fancy New", "Lynnwood City Center"]);
+        assert_eq!(lines.names(Route::Line2), ["Redmond Technology", "Fancy New", "Lynnwood City Center"]);
+        let new = &lines.line_1[1];
+        assert_eq!((new.ident.as_str(), new.stop_id.as_str(), new.existing), ("FANCY_NEW", "40_NEW", false));
+        assert_eq!(new.platform_ids, ["40_NEW-T1", "40_NEW-T2"]);
+        assert_eq!(lines.added().count(), 1);
+        // shared stations are listed once, though the registry has them on both lines
+        assert_eq!(lines.removed(), [
+            "Federal Way Downtown", "Star Lake", "Kent Des Moines", "SeaTac/Airport", "Tukwila Int'l Blvd",
+            "Rainier Beach", "Othello", "Columbia City", "Mount Baker", "Beacon Hill", "SODO", "Stadium",
+            "Int'l Dist/Chinatown", "Pioneer Square", "Symphony", "Westlake", "Capitol Hill",
+            "Univ of Washington", "U District", "Roosevelt", "Northgate", "Pinehurst",
+            "Shoreline South/148th", "Shoreline North/185th", "Mountlake Terrace", "Downtown Redmond",
+            "Marymoor Village", "Overlake Village", "BelRed", "Spring District", "Wilburton",
+            "Bellevue Downtown", "East Main", "South Bellevue", "Mercer Island", "Judkins Park",
+        ]);
+
+        let source = lines.registry_source();
+        assert!(source.contains("pub const ANGLE_LAKE: Station = station(\"40_S01\", \"Angle Lake\", &[\"40_99913\", \"40_99914\"]);"));
+        assert!(source.contains("pub const LINE_2: [Station; 3] = [\n    REDMOND_TECH,\n    FANCY_NEW,\n    LYNNWOOD_CC,\n];"));
+    }
+}
@@ -0,0 +1,361 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    constants::{Destination, CID, JUDKINS_PARK},
+    display::Route,
+    error::{Error, LayoutErr}
+};
+
+pub use geo::GeoLed;
+pub use validation::{Issue, Severity, ValidationReport};
+
+mod geo;
+mod validation;
+
+/// Layout for the board in the README, used when no layout file is configured.
+pub const DEFAULT_MAP_LAYOUT: &str = include_str!("../layouts/map.toml");
+
+/// ((south index, LEDs since previous stop), (north index, LEDs since previous stop))
+pub type StationMapIdx = ((usize, usize), (usize, usize));
+
+/// LED indices of one station on the map display.
+///
+/// "LEDs since previous stop" are the in-between LEDs next to the station on that side, counted from
+/// the stop before it for a train travelling on that side.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StationLeds {
+    pub station: String,
+    /// (south/east index, LEDs since previous stop)
+    pub south: (usize, usize),
+    /// (north/west index, LEDs since previous stop)
+    pub north: (usize, usize),
+}
+
+impl StationLeds {
+    pub fn map_idx(&self) -> StationMapIdx {
+        (self.south, self.north)
+    }
+
+    pub fn side(&self, side: Side) -> (usize, usize) {
+        match side {
+            Side::South => self.south,
+            Side::North => self.north,
+        }
+    }
+}
+
+/// Side of the board a train is drawn on: `South` for trains heading away from Lynnwood,
+/// `North` for trains heading towards it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Side {
+    South,
+    North,
+}
+
+impl Side {
+    pub fn for_destination(destination: Destination) -> Self {
+        match destination {
+            Destination::LynnwoodCC => Side::North,
+            Destination::FederalWayDT | Destination::RedmondDT => Side::South,
+        }
+    }
+}
+
+/// Per-line station LED indices for the map display, as read from a layout file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MapLayout {
+    total_leds: usize,
+    line_1: Vec<StationLeds>,
+    line_2: Vec<StationLeds>,
+    /// LED that shows when the data is stale or missing, if the board has a spare one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status_led: Option<usize>,
+    /// Coordinates of LEDs, for placing trains by where they are rather than their next stop.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    geo: Vec<GeoLed>,
+    #[serde(skip)]
+    line_1_lookup: HashMap<String, usize>,
+    #[serde(skip)]
+    line_2_lookup: HashMap<String, usize>,
+}
+
+impl MapLayout {
+    pub fn new(total_leds: usize, line_1: Vec<StationLeds>, line_2: Vec<StationLeds>) -> Self {
+        let mut layout = Self {
+            total_leds,
+            line_1,
+            line_2,
+            status_led: None,
+            geo: vec![],
+            line_1_lookup: HashMap::new(),
+            line_2_lookup: HashMap::new(),
+        };
+        layout.build_lookups();
+        layout
+    }
+
+    /// A starting layout with `leds_between` LEDs between every pair of stops, wired like the bundled
+    /// board: the 1 Line runs out along the south side and back along the north side, then the
+    /// 2 Line branch runs out along its north side and back along its south side. Lines are in the
+    /// order Lynnwood-bound trains visit their stations, and the 2 Line shares the 1 Line's LEDs up
+    /// to the station where it splits off.
+    pub fn skeleton(line_1: &[&str], line_2: &[&str], leds_between: usize) -> Self {
+        let mut idx = 0;
+        let mut line_1_leds: Vec<StationLeds> = line_1.iter()
+            .map(|name| StationLeds { station: name.to_string(), south: (0, 0), north: (0, 0) })
+            .collect();
+
+        // the 1 Line ascends on both sides, with a station's in-between LEDs after it, and trains
+        // start at the far end of each side with nothing before them
+        let last = line_1_leds.len().saturating_sub(1);
+        for (i, leds) in line_1_leds.iter_mut().enumerate() {
+            let before = if i == last { 0 } else { leds_between };
+            leds.south = (idx, before);
+            idx += 1 + before;
+        }
+        for (i, leds) in line_1_leds.iter_mut().enumerate().rev() {
+            let before = if i == 0 { 0 } else { leds_between };
+            leds.north = (idx, before);
+            idx += 1 + before;
+        }
+
+        // the 2 Line branch descends, with a station's in-between LEDs before it
+        let branch: Vec<&str> = line_2.iter().copied().filter(|name| !line_1.contains(name)).collect();
+        let mut branch_north = HashMap::new();
+        for (i, name) in branch.iter().enumerate() {
+            let before = if i == 0 { 0 } else { leds_between };
+            idx += before;
+            branch_north.insert(*name, (idx, before));
+            idx += 1;
+        }
+        // Lynnwood-bound LEDs between the branch and the split, see `crosses_split`
+        let crossing = if branch.is_empty() { 0 } else { leds_between };
+        idx += crossing;
+        let mut branch_south = HashMap::new();
+        for name in branch.iter().rev() {
+            idx += leds_between;
+            branch_south.insert(*name, (idx, leds_between));
+            idx += 1;
+        }
+
+        let split = line_2.iter().find(|name| line_1.contains(name));
+        let line_2_leds = line_2.iter().map(|name| {
+            match line_1_leds.iter().find(|leds| leds.station == *name) {
+                Some(leds) if Some(name) == split => StationLeds { north: (leds.north.0, crossing), ..leds.clone() },
+                Some(leds) => leds.clone(),
+                None => StationLeds { station: name.to_string(), south: branch_south[name], north: branch_north[name] },
+            }
+        }).collect();
+
+        Self::new(idx, line_1_leds, line_2_leds)
+    }
+
+    /// Loads the layout file named in `config`, or the default layout if there isn't one, and
+    /// rejects it if validation finds any errors.
+    pub fn load(config: &Config) -> Result<Self, Error> {
+        let layout = match config.map_layout() {
+            Some(path) => Self::from_file(path)?,
+            None => Self::from_toml_str(DEFAULT_MAP_LAYOUT)?,
+        };
+
+        let report = layout.validate();
+        for issue in report.issues() {
+            warn!("{issue}");
+        }
+        if report.has_errors() {
+            return Err(Error::layout_error(LayoutErr::Invalid(report)));
+        }
+        Ok(layout)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let toml = fs::read_to_string(path)?;
+        info!("loaded map layout from {}", path.display());
+        Self::from_toml_str(&toml)
+    }
+
+    /// Parses a layout without validating it, see `validate`.
+    pub fn from_toml_str(toml: &str) -> Result<Self, Error> {
+        let mut layout: MapLayout = toml::from_str(toml).map_err(|e| Error::layout_error(LayoutErr::Toml(e)))?;
+        layout.build_lookups();
+        Ok(layout)
+    }
+
+    /// Checks the layout for LEDs that are out of range, overlap, or are never lit.
+    pub fn validate(&self) -> ValidationReport {
+        validation::validate(self)
+    }
+
+    pub fn to_toml_string(&self) -> String {
+        toml::to_string(self).expect("layout is always serializable")
+    }
+
+    pub fn total_leds(&self) -> usize {
+        self.total_leds
+    }
+
+    pub fn status_led(&self) -> Option<usize> {
+        self.status_led
+    }
+
+    pub fn with_status_led(self, status_led: usize) -> Self {
+        Self { status_led: Some(status_led), ..self }
+    }
+
+    pub fn line(&self, route: Route) -> &[StationLeds] {
+        match route {
+            Route::Line1 => &self.line_1,
+            Route::Line2 => &self.line_2,
+        }
+    }
+
+    pub fn contains(&self, route: Route, station: &str) -> bool {
+        self.lookup(route).contains_key(station)
+    }
+
+    pub fn station(&self, route: Route, station: &str) -> Option<&StationLeds> {
+        self.lookup(route).get(station).map(|&i| &self.line(route)[i])
+    }
+
+    /// Index of the station LED for trains on `route` headed to `destination`.
+    pub fn station_idx(&self, route: Route, destination: Destination, station: &str) -> Option<usize> {
+        Some(self.station(route, station)?.side(Side::for_destination(destination)).0)
+    }
+
+    /// Index of the in-between LED nearest `station` for trains on `route` headed to `destination`,
+    /// or `None` if it would fall off the start of the strip.
+    pub fn idx_before_stop(&self, route: Route, destination: Destination, station: &str) -> Option<usize> {
+        let (idx, leds_before) = self.station(route, station)?.side(Side::for_destination(destination));
+        if crosses_split(route, destination, station) {
+            // Lynnwood-bound 2 Line LEDs before CID are on the Judkins Park side of the split
+            return Some(self.station(Route::Line2, JUDKINS_PARK)?.north.0 + leds_before);
+        }
+
+        if self.segment_ascends(route, destination, station) {
+            Some(idx + 1)
+        } else {
+            idx.checked_sub(1)
+        }
+    }
+
+    /// Number of in-between LEDs before `station` for trains on `route` headed to `destination`.
+    pub fn leds_before_stop(&self, route: Route, destination: Destination, station: &str) -> Option<usize> {
+        Some(self.station(route, station)?.side(Side::for_destination(destination)).1)
+    }
+
+    /// The in-between LEDs before `station` for trains on `route` headed to `destination`, starting
+    /// with the one nearest the station. Returns `None` if the station isn't in the layout or the
+    /// LEDs would run off either end of the strip.
+    pub fn segment_leds(&self, route: Route, destination: Destination, station: &str) -> Option<Vec<usize>> {
+        let mut idx = self.idx_before_stop(route, destination, station)?;
+        let count = self.leds_before_stop(route, destination, station)?;
+        let ascending = self.segment_ascends(route, destination, station);
+
+        let mut leds = Vec::with_capacity(count);
+        for i in 0..count {
+            if i > 0 {
+                idx = if ascending { idx + 1 } else { idx.checked_sub(1)? };
+            }
+            if idx >= self.total_leds {
+                return None;
+            }
+            leds.push(idx);
+        }
+        Some(leds)
+    }
+
+    /// Lynnwood to CID, add 1; Judkins Park to Redmond (and back to CID), subtract 1
+    fn segment_ascends(&self, route: Route, destination: Destination, station: &str) -> bool {
+        match route {
+            Route::Line1 => true,
+            Route::Line2 => self.contains(Route::Line1, station) && !crosses_split(route, destination, station),
+        }
+    }
+
+    /// Every (station name, LED index) pair in the layout, for both lines and directions.
+    pub fn all_station_idxs(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.line_1.iter().chain(self.line_2.iter())
+            .flat_map(|s| [(s.station.as_str(), s.south.0), (s.station.as_str(), s.north.0)])
+    }
+
+    fn lookup(&self, route: Route) -> &HashMap<String, usize> {
+        match route {
+            Route::Line1 => &self.line_1_lookup,
+            Route::Line2 => &self.line_2_lookup,
+        }
+    }
+
+    fn build_lookups(&mut self) {
+        self.line_1_lookup = self.line_1.iter().enumerate().map(|(i, s)| (s.station.clone(), i)).collect();
+        self.line_2_lookup = self.line_2.iter().enumerate().map(|(i, s)| (s.station.clone(), i)).collect();
+    }
+}
+
+/// The 2 Line splits from the 1 Line at CID, so the LEDs before it don't sit next to its station LED.
+fn crosses_split(route: Route, destination: Destination, station: &str) -> bool {
+    route == Route::Line2 && destination == Destination::LynnwoodCC && station == CID
+}
+
+impl Default for MapLayout {
+    fn default() -> Self {
+        Self::from_toml_str(DEFAULT_MAP_LAYOUT).expect("default map layout is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::CID;
+
+    #[test]
+    /// test that shared stops on the 1 and 2 line (except CID) match values
+    fn test_line_map_vals_match() {
+        let layout = MapLayout::default();
+        for leds in layout.line(Route::Line1) {
+            if let Some(line_2_leds) = layout.station(Route::Line2, &leds.station) {
+                if leds.station != CID {
+                    assert_eq!(leds, line_2_leds);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_segment_leds() {
+        let layout = MapLayout::default();
+        assert_eq!(layout.segment_leds(Route::Line1, Destination::FederalWayDT, "SODO"), Some(vec![52]));
+        // Lynnwood-bound 2 Line trains count down from the Judkins Park side of the split
+        assert_eq!(layout.segment_leds(Route::Line2, Destination::LynnwoodCC, CID), Some(vec![254, 253, 252, 251, 250, 249]));
+        assert_eq!(layout.segment_leds(Route::Line2, Destination::LynnwoodCC, "Judkins Park"), Some(vec![247, 246, 245, 244, 243, 242, 241]));
+        assert_eq!(layout.segment_leds(Route::Line2, Destination::RedmondDT, "Mercer Island"), Some(vec![268, 267, 266, 265, 264, 263, 262]));
+        assert_eq!(layout.segment_leds(Route::Line1, Destination::LynnwoodCC, "Judkins Park"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let layout = MapLayout::default();
+        let reloaded = MapLayout::from_toml_str(&layout.to_toml_string()).unwrap();
+        assert_eq!(layout.total_leds(), reloaded.total_leds());
+        assert_eq!(layout.line(Route::Line2), reloaded.line(Route::Line2));
+    }
+
+    #[test]
+    fn test_skeleton_is_valid() {
+        let names = |route| crate::stations::line(route).iter().map(|s| s.name).collect::<Vec<_>>();
+        let layout = MapLayout::skeleton(&names(Route::Line1), &names(Route::Line2), 2);
+        let report = layout.validate();
+        assert!(report.issues().is_empty(), "{report}");
+        assert_eq!(layout.segment_leds(Route::Line2, Destination::LynnwoodCC, CID).map(|leds| leds.len()), Some(2));
+    }
+
+    #[test]
+    fn test_default_layout_is_valid() {
+        let report = MapLayout::default().validate();
+        assert!(!report.has_errors(), "{report}");
+    }
+}
@@ -0,0 +1,125 @@
+//! Geographic placement: LEDs given coordinates in the layout's `geo` list are joined, in the
+//! order trains pass them, into a polyline for each direction of each line.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{constants::Destination, display::Route};
+
+use super::MapLayout;
+
+/// Trains further than this from their line's LEDs aren't placed.
+const MAX_DISTANCE_M: f64 = 1000.0;
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// An LED index and its (latitude, longitude).
+type Point = (usize, (f64, f64));
+
+/// An LED with the coordinates it stands for on the map.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GeoLed {
+    pub led: usize,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl MapLayout {
+    pub fn geo(&self) -> &[GeoLed] {
+        &self.geo
+    }
+
+    pub fn with_geo(self, geo: Vec<GeoLed>) -> Self {
+        Self { geo, ..self }
+    }
+
+    /// Every LED a train on `route` headed to `destination` passes, in order: the in-between LEDs
+    /// before each station, then the station LED.
+    pub fn leds_along(&self, route: Route, destination: Destination) -> Vec<usize> {
+        let stations: Vec<&str> = self.line(route).iter().map(|leds| leds.station.as_str()).collect();
+        let stations: Vec<&str> = match destination {
+            Destination::LynnwoodCC => stations,
+            Destination::FederalWayDT | Destination::RedmondDT => stations.into_iter().rev().collect(),
+        };
+
+        let mut leds = vec![];
+        for station in stations {
+            if let Some(segment) = self.segment_leds(route, destination, station) {
+                leds.extend(segment.into_iter().rev());
+            }
+            leds.extend(self.station_idx(route, destination, station));
+        }
+        leds
+    }
+
+    /// The LED nearest `(lat, lon)` along the polyline for trains on `route` headed to
+    /// `destination`, or `None` if no LEDs on it have coordinates or the point is too far away.
+    pub fn nearest_led(&self, route: Route, destination: Destination, lat: f64, lon: f64) -> Option<usize> {
+        let coords: HashMap<usize, (f64, f64)> = self.geo.iter().map(|geo| (geo.led, (geo.lat, geo.lon))).collect();
+        let polyline: Vec<Point> = self.leds_along(route, destination).into_iter()
+            .filter_map(|idx| Some((idx, *coords.get(&idx)?)))
+            .collect();
+        nearest_on_polyline(&polyline, (lat, lon))
+    }
+}
+
+/// The LED at whichever end of the nearest polyline segment is closer to `point`.
+fn nearest_on_polyline(polyline: &[Point], point: (f64, f64)) -> Option<usize> {
+    // meters east and north of `point`, which is close enough to flat over a few kilometers
+    let project = |(lat, lon): (f64, f64)| {
+        let x = (lon - point.1).to_radians() * point.0.to_radians().cos() * EARTH_RADIUS_M;
+        let y = (lat - point.0).to_radians() * EARTH_RADIUS_M;
+        (x, y)
+    };
+    let segments: Vec<(&Point, &Point)> = match polyline {
+        [only] => vec![(only, only)],
+        _ => polyline.windows(2).map(|pair| (&pair[0], &pair[1])).collect(),
+    };
+
+    let mut nearest: Option<(f64, usize)> = None;
+    for ((a_idx, a), (b_idx, b)) in segments {
+        let ((ax, ay), (bx, by)) = (project(*a), project(*b));
+        let (dx, dy) = (bx - ax, by - ay);
+        let length_squared = dx * dx + dy * dy;
+        let t = if length_squared == 0.0 { 0.0 } else { (-ax * dx - ay * dy) / length_squared }.clamp(0.0, 1.0);
+        let distance = (ax + t * dx).hypot(ay + t * dy);
+        let idx = if t < 0.5 { *a_idx } else { *b_idx };
+        if nearest.map_or(true, |(best, _)| distance < best) {
+            nearest = Some((distance, idx));
+        }
+    }
+    nearest.filter(|(distance, _)| *distance <= MAX_DISTANCE_M).map(|(_, idx)| idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_led() {
+        // Westlake, then two LEDs north along 3rd Ave, then Symphony
+        let toml = r#"
+            total_leds = 8
+            line_1 = [
+                { station = "Symphony", south = [6, 0], north = [5, 0] },
+                { station = "Westlake", south = [7, 0], north = [2, 2] },
+            ]
+            line_2 = []
+            geo = [
+                { led = 2, lat = 47.6114, lon = -122.3372 },
+                { led = 4, lat = 47.6094, lon = -122.3355 },
+                { led = 5, lat = 47.6080, lon = -122.3360 },
+            ]
+        "#;
+        let layout = MapLayout::from_toml_str(toml).unwrap();
+        assert_eq!(layout.leds_along(Route::Line1, Destination::LynnwoodCC), vec![5, 4, 3, 2]);
+
+        let nearest = |lat, lon| layout.nearest_led(Route::Line1, Destination::LynnwoodCC, lat, lon);
+        assert_eq!(nearest(47.6112, -122.3370), Some(2));
+        assert_eq!(nearest(47.6096, -122.3357), Some(4));
+        assert_eq!(nearest(47.6082, -122.3360), Some(5));
+        assert_eq!(nearest(47.70, -122.30), None);
+        assert_eq!(layout.nearest_led(Route::Line1, Destination::FederalWayDT, 47.6112, -122.3370), None);
+    }
+}
@@ -0,0 +1,348 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use crate::{constants::{Destination, CID}, display::Route, stations};
+
+use super::{MapLayout, Side};
+
+/// Every direction a train can travel in on each line.
+const DIRECTIONS: [(Route, Destination); 4] = [
+    (Route::Line1, Destination::LynnwoodCC),
+    (Route::Line1, Destination::FederalWayDT),
+    (Route::Line2, Destination::LynnwoodCC),
+    (Route::Line2, Destination::RedmondDT),
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    /// The map display would draw over the wrong LED or panic.
+    Error,
+    /// Probably unintended, but harmless to the display.
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    severity: Severity,
+    message: String,
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+/// Result of checking a `MapLayout`; `Display` prints a readable report.
+#[derive(Clone, Debug)]
+pub struct ValidationReport {
+    total_leds: usize,
+    line_1_stations: usize,
+    line_2_stations: usize,
+    issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors().count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    fn error(&mut self, message: String) {
+        self.issues.push(Issue { severity: Severity::Error, message });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.issues.push(Issue { severity: Severity::Warning, message });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "map layout: {} LEDs, {} 1 Line stations, {} 2 Line stations",
+            self.total_leds, self.line_1_stations, self.line_2_stations)?;
+        for issue in &self.issues {
+            writeln!(f, "  {issue}")?;
+        }
+        let errors = self.error_count();
+        write!(f, "{} error(s), {} warning(s)", errors, self.issues.len() - errors)
+    }
+}
+
+pub(super) fn validate(layout: &MapLayout) -> ValidationReport {
+    let mut report = ValidationReport {
+        total_leds: layout.total_leds,
+        line_1_stations: layout.line_1.len(),
+        line_2_stations: layout.line_2.len(),
+        issues: vec![],
+    };
+
+    check_duplicates(layout, &mut report);
+    check_known_stations(layout, &mut report);
+    check_shared_stations(layout, &mut report);
+    let station_leds = check_station_leds(layout, &mut report);
+    let segment_leds = check_segments(layout, &station_leds, &mut report);
+    check_status_led(layout, &station_leds, &segment_leds, &mut report);
+    check_geo(layout, &station_leds, &segment_leds, &mut report);
+    check_unlit(layout, &station_leds, &segment_leds, &mut report);
+
+    report
+}
+
+fn line_name(route: Route) -> &'static str {
+    match route {
+        Route::Line1 => "1 Line",
+        Route::Line2 => "2 Line",
+    }
+}
+
+fn check_duplicates(layout: &MapLayout, report: &mut ValidationReport) {
+    for route in [Route::Line1, Route::Line2] {
+        let mut seen = HashSet::new();
+        for leds in layout.line(route) {
+            if !seen.insert(leds.station.as_str()) {
+                report.error(format!("{} is listed more than once on the {}", leds.station, line_name(route)));
+            }
+        }
+    }
+}
+
+/// Trains are only ever placed at stations in the registry, so any other station is never lit.
+fn check_known_stations(layout: &MapLayout, report: &mut ValidationReport) {
+    for route in [Route::Line1, Route::Line2] {
+        for leds in layout.line(route) {
+            if !stations::contains(route, &leds.station) {
+                report.warning(format!("{} is not a {} station", leds.station, line_name(route)));
+            }
+        }
+    }
+}
+
+/// Stations on both lines share LEDs, except CID where the 2 Line splits from the 1 Line.
+fn check_shared_stations(layout: &MapLayout, report: &mut ValidationReport) {
+    for leds in layout.line(Route::Line1) {
+        if leds.station == CID {
+            continue;
+        }
+        if let Some(line_2_leds) = layout.station(Route::Line2, &leds.station) {
+            if leds.map_idx() != line_2_leds.map_idx() {
+                report.error(format!("{} has different LEDs on the 1 Line {:?} and 2 Line {:?}",
+                    leds.station, leds.map_idx(), line_2_leds.map_idx()));
+            }
+        }
+    }
+}
+
+/// Returns the station name for every station LED that is on the strip.
+fn check_station_leds(layout: &MapLayout, report: &mut ValidationReport) -> HashMap<usize, String> {
+    let mut station_leds: HashMap<usize, String> = HashMap::new();
+    for route in [Route::Line1, Route::Line2] {
+        for leds in layout.line(route) {
+            if leds.south.0 == leds.north.0 {
+                report.error(format!("{} uses LED {} for both sides", leds.station, leds.south.0));
+            }
+            for (side, (idx, _)) in [(Side::South, leds.south), (Side::North, leds.north)] {
+                if idx >= layout.total_leds {
+                    report.error(format!("{} {:?} LED {} is beyond the end of the {} LED strip",
+                        leds.station, side, idx, layout.total_leds));
+                    continue;
+                }
+                match station_leds.get(&idx) {
+                    Some(other) if *other != leds.station => {
+                        report.error(format!("{} {:?} LED {} is also used by {}", leds.station, side, idx, other));
+                    },
+                    Some(_) => {},
+                    None => {
+                        station_leds.insert(idx, leds.station.clone());
+                    },
+                }
+            }
+        }
+    }
+    station_leds
+}
+
+/// Returns which segment(s) each in-between LED belongs to, keyed by `(side, next stop)` so that
+/// the shared 1 and 2 Line segments don't count as overlapping.
+fn check_segments(
+    layout: &MapLayout,
+    station_leds: &HashMap<usize, String>,
+    report: &mut ValidationReport
+) -> HashMap<usize, (Side, String)> {
+    let mut segment_leds: HashMap<usize, (Side, String)> = HashMap::new();
+    for (route, destination) in DIRECTIONS {
+        let side = Side::for_destination(destination);
+        for leds in layout.line(route) {
+            let desc = format!("{} ({}, {:?}-bound)", leds.station, line_name(route), destination);
+            let Some(segment) = layout.segment_leds(route, destination, &leds.station) else {
+                report.error(format!("{desc} has {} LEDs before it that run off the end of the strip", leds.side(side).1));
+                continue;
+            };
+
+            for idx in segment {
+                if let Some(other) = station_leds.get(&idx) {
+                    report.error(format!("{desc} in-between LED {idx} overlaps the station LED for {other}"));
+                    continue;
+                }
+                match segment_leds.get(&idx) {
+                    Some((other_side, other)) if *other_side != side || *other != leds.station => {
+                        report.error(format!("{desc} in-between LED {idx} is also used before {other} ({other_side:?} side)"));
+                    },
+                    Some(_) => {},
+                    None => {
+                        segment_leds.insert(idx, (side, leds.station.clone()));
+                    },
+                }
+            }
+        }
+    }
+    segment_leds
+}
+
+fn check_status_led(
+    layout: &MapLayout,
+    station_leds: &HashMap<usize, String>,
+    segment_leds: &HashMap<usize, (Side, String)>,
+    report: &mut ValidationReport
+) {
+    let Some(idx) = layout.status_led else {
+        return;
+    };
+    if idx >= layout.total_leds {
+        report.error(format!("status LED {idx} is beyond the end of the {} LED strip", layout.total_leds));
+    } else if let Some(station) = station_leds.get(&idx) {
+        report.error(format!("status LED {idx} is the station LED for {station}"));
+    } else if let Some((side, station)) = segment_leds.get(&idx) {
+        report.error(format!("status LED {idx} is an in-between LED before {station} ({side:?} side)"));
+    }
+}
+
+/// Coordinates only place trains on station and in-between LEDs, since those are the ones on a
+/// line's path.
+fn check_geo(
+    layout: &MapLayout,
+    station_leds: &HashMap<usize, String>,
+    segment_leds: &HashMap<usize, (Side, String)>,
+    report: &mut ValidationReport
+) {
+    let mut seen = HashSet::new();
+    for geo in &layout.geo {
+        if !seen.insert(geo.led) {
+            report.error(format!("geo LED {} is listed more than once", geo.led));
+        } else if geo.led >= layout.total_leds {
+            report.error(format!("geo LED {} is beyond the end of the {} LED strip", geo.led, layout.total_leds));
+        } else if !station_leds.contains_key(&geo.led) && !segment_leds.contains_key(&geo.led) {
+            report.warning(format!("geo LED {} is not a station or in-between LED, so no train is placed there", geo.led));
+        }
+    }
+}
+
+/// LEDs that are neither a station, the status LED, nor reachable as an in-between LED are never
+/// lit.
+fn check_unlit(
+    layout: &MapLayout,
+    station_leds: &HashMap<usize, String>,
+    segment_leds: &HashMap<usize, (Side, String)>,
+    report: &mut ValidationReport
+) {
+    let mut start = None;
+    for idx in 0..=layout.total_leds {
+        let lit = idx == layout.total_leds || layout.status_led == Some(idx)
+            || station_leds.contains_key(&idx) || segment_leds.contains_key(&idx);
+        match (lit, start) {
+            (false, None) => start = Some(idx),
+            (true, Some(first)) => {
+                if first == idx - 1 {
+                    report.warning(format!("LED {first} is never lit"));
+                } else {
+                    report.warning(format!("LEDs {first}-{} are never lit", idx - 1));
+                }
+                start = None;
+            },
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlapping_segment_is_error() {
+        let toml = r#"
+            total_leds = 10
+            line_1 = [
+                { station = "Federal Way Downtown", south = [1, 3], north = [9, 0] },
+                { station = "Star Lake",            south = [4, 2], north = [7, 1] },
+            ]
+            line_2 = []
+        "#;
+        let report = MapLayout::from_toml_str(toml).unwrap().validate();
+        assert_eq!(report.error_count(), 1, "{report}");
+        assert!(report.errors().next().unwrap().message().contains("overlaps the station LED for Star Lake"));
+    }
+
+    #[test]
+    fn test_status_led_must_be_spare() {
+        let toml = r#"
+            total_leds = 6
+            status_led = 5
+            line_1 = [
+                { station = "Federal Way Downtown", south = [0, 0], north = [3, 1] },
+                { station = "Star Lake",            south = [1, 0], north = [2, 0] },
+            ]
+            line_2 = []
+        "#;
+        let layout = MapLayout::from_toml_str(toml).unwrap();
+        assert!(layout.validate().issues().is_empty(), "{}", layout.validate());
+
+        let report = layout.with_status_led(2).validate();
+        assert_eq!(report.error_count(), 1, "{report}");
+        assert!(report.errors().next().unwrap().message().contains("is the station LED for Star Lake"));
+    }
+
+    #[test]
+    fn test_geo_leds_must_be_on_a_line() {
+        let toml = r#"
+            total_leds = 5
+            line_1 = [
+                { station = "Federal Way Downtown", south = [0, 0], north = [3, 0] },
+                { station = "Star Lake",            south = [1, 0], north = [2, 0] },
+            ]
+            line_2 = []
+            geo = [
+                { led = 2, lat = 47.3, lon = -122.3 },
+                { led = 4, lat = 47.3, lon = -122.3 },
+                { led = 5, lat = 47.3, lon = -122.3 },
+                { led = 2, lat = 47.3, lon = -122.3 },
+            ]
+        "#;
+        let report = MapLayout::from_toml_str(toml).unwrap().validate();
+        assert_eq!(report.error_count(), 2, "{report}");
+        assert!(report.issues().iter().any(|issue| issue.message() == "geo LED 4 is not a station or in-between LED, so no train is placed there"));
+    }
+}
@@ -1,24 +1,51 @@
-#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+use std::{fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct Led {
     value: (u8, u8, u8)
 }
 
-const ULTRA_DIM: u8 = 2;
-const DIM_MAJOR: u8 = 20;
-const DIM_MINOR: u8 = 10;
-const DIM_EQ_MIX: u8 = DIM_MAJOR;
-const REG_MAJOR: u8 = 30;
-const REG_MINOR: u8 = 20;
-const REG_EQ_MIX: u8 = REG_MAJOR;
-
+// Colors are gamma encoded at full scale, like sRGB: the brightest is 255, and
+// `display::Brightness` turns them into what's written to the LEDs.
+//
+// The palette below is at full scale. The lines' colors are used as they are, but the board has
+// always drawn buffers, status patterns and empty stations far dimmer than trains, so those are
+// the palette at one of these levels, out of 255. At the default brightness and gamma they come
+// out as the values the board used to be tuned to by hand.
+const REGULAR: u8 = 102;
+const DIM: u8 = 85;
+const ULTRA_DIM: u8 = 30;
+
+const RED: Led = Led::from(255, 0, 0);
+const GREEN: Led = Led::from(0, 255, 0);
+const BLUE: Led = Led::from(0, 0, 255);
+const CYAN: Led = Led::from(0, 255, 255);
+/// The hue of the dim cyan, which was tuned separately.
+const PALE_CYAN: Led = Led::from(135, 186, 255);
+const PURPLE: Led = Led::from(255, 0, 255);
+const ORANGE: Led = Led::from(212, 255, 0);
+/// The hue of the dim orange, which was tuned separately.
+const DEEP_ORANGE: Led = Led::from(186, 255, 0);
+const YELLOW: Led = Led::from(255, 255, 0);
+const WHITE: Led = Led::from(255, 255, 255);
 
 impl Led {
-    pub fn from(r: u8, g: u8, b: u8) -> Self {
+    pub const fn from(r: u8, g: u8, b: u8) -> Self {
         Self {
             value: (r, g, b)
         }
     }
 
+    /// Each channel scaled to `level` out of 255, rounded.
+    pub const fn at_level(&self, level: u8) -> Self {
+        const fn scale(c: u8, level: u8) -> u8 {
+            ((c as u16 * level as u16 + 127) / 255) as u8
+        }
+        Self::from(scale(self.value.0, level), scale(self.value.1, level), scale(self.value.2, level))
+    }
+
     pub fn r(&self) -> u8 {
         self.value.0
     }
@@ -31,8 +58,10 @@ impl Led {
         self.value.2
     }
 
-    pub fn eq(&self, other: &Self) -> bool {
-        self.value == other.value
+    /// About half as bright once gamma corrected, without turning a lit channel off.
+    pub fn dimmed(&self) -> Self {
+        let dim = |c: u8| if c == 0 { 0 } else { ((c as u16 * 3 / 4) as u8).max(1) };
+        Self::from(dim(self.r()), dim(self.g()), dim(self.b()))
     }
 
     pub fn add_tuple(&mut self, rgb: (u8, u8, u8)) {
@@ -53,25 +82,25 @@ impl Led {
 
     pub const fn ln_1_at_station() -> Self {
         Self {
-            value: (61, 174, 43)
+            value: (141, 228, 121)
         }
     }
 
     pub const fn ln_1_between_stations() -> Self {
         Self {
-            value: (6, 17, 4)
+            value: (49, 79, 41)
         }
     }
 
     pub const fn ln_2_at_station() -> Self {
         Self {
-            value: (0, 160, 223)
+            value: (0, 219, 255)
         }
     }
 
     pub const fn ln_2_between_stations() -> Self {
         Self {
-            value: (0, 16, 22)
+            value: (0, 77, 89)
         }
     }
 
@@ -84,84 +113,91 @@ impl Led {
     }
 
     pub const fn red() -> Self {
-        Self {
-            value: (REG_MAJOR, 0, 0)
-        }
+        RED.at_level(REGULAR)
     }
 
     pub const fn green() -> Self {
-        Self {
-            value: (0, REG_MAJOR, 0)
-        }
+        GREEN.at_level(REGULAR)
     }
 
     pub const fn dull_green() -> Self {
-        Self {
-            value: (0, DIM_MAJOR, 0)
-        }
+        GREEN.at_level(DIM)
     }
 
     pub const fn blue() -> Self {
-        Self {
-            value: (0, 0, REG_MAJOR)
-        }
+        BLUE.at_level(REGULAR)
     }
 
     pub const fn dull_blue() -> Self {
-        Self {
-            value: (0, 0, DIM_MAJOR)
-        }
+        BLUE.at_level(DIM)
     }
 
     pub const fn cyan() -> Self {
-        Self {
-            value: (0, REG_EQ_MIX, REG_EQ_MIX)
-        }
+        CYAN.at_level(REGULAR)
     }
 
     pub const fn dull_cyan() -> Self {
-        Self {
-            value: (5, DIM_MINOR, DIM_MAJOR)
-        }
+        PALE_CYAN.at_level(DIM)
     }
 
     pub const fn purple() -> Self {
-        Self {
-            value: (REG_EQ_MIX, 0, REG_EQ_MIX)
-        }
+        PURPLE.at_level(REGULAR)
     }
 
     pub const fn dull_purple() -> Self {
-        Self {
-            value: (DIM_EQ_MIX, 0, DIM_EQ_MIX)
-        }
+        PURPLE.at_level(DIM)
     }
 
     pub const fn orange() -> Self {
-        Self {
-            value: (REG_MINOR, REG_MAJOR, 0)
-        }
+        ORANGE.at_level(REGULAR)
     }
 
     pub const fn dull_orange() -> Self {
-        Self {
-            value: (DIM_MINOR, DIM_MAJOR, 0)
-        }
+        DEEP_ORANGE.at_level(DIM)
     }
 
     pub const fn dull_yellow() -> Self {
-        Self {
-            value: (DIM_EQ_MIX, DIM_EQ_MIX, 0)
-        }
+        YELLOW.at_level(DIM)
     }
 
     pub const fn dull_white() -> Self {
-        Self {
-            value: (ULTRA_DIM, ULTRA_DIM, ULTRA_DIM)
-        }
+        WHITE.at_level(ULTRA_DIM)
     }
 
     pub const fn as_tuple(&self) -> (u8, u8, u8) {
         self.value
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseLedErr;
+
+/// Parses a hex color, e.g. `#3dae2b`.
+impl FromStr for Led {
+    type Err = ParseLedErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii()).ok_or(ParseLedErr)?;
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| ParseLedErr);
+        Ok(Self::from(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl fmt::Display for Led {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r(), self.g(), self.b())
+    }
+}
+
+impl Serialize for Led {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Led {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        hex.parse().map_err(|_| de::Error::custom(format!("invalid color {hex:?}, expected #rrggbb")))
+    }
+}
@@ -1,10 +1,22 @@
+pub mod calibration;
+pub mod config;
 mod constants;
 mod data_parser;
 pub mod data_retriever;
 pub mod display;
-pub mod env;
 pub mod error;
+pub mod gtfs_rt;
+#[cfg(feature = "cli")]
+pub mod gtfs_static;
+pub mod layout;
 pub mod led;
+pub mod local_time;
+pub mod retry;
 pub mod spi_adapter;
+pub mod stations;
+#[cfg(test)]
+mod test_data;
+pub mod theme;
+mod tracker;
 mod train;
 mod trips_for_route_types;
\ No newline at end of file
@@ -0,0 +1,166 @@
+//! Time of day on the board's wall clock, for the night and quiet hours.
+//!
+//! There's no time zone database on the ESP32, so local time is a fixed UTC offset with US daylight
+//! saving time optionally applied on top.
+
+use std::{fmt, str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+/// 2020-01-01; a clock before this hasn't been set yet, e.g. an ESP32 that hasn't synced over SNTP.
+const EARLIEST_SET_CLOCK: u64 = 1_577_836_800;
+
+/// Minutes since local midnight at `now`, or `None` if the clock hasn't been set.
+pub fn local_minutes(now: SystemTime, utc_offset_hours: i8, daylight_saving: bool) -> Option<u16> {
+    let secs = now.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    if secs < EARLIEST_SET_CLOCK {
+        return None;
+    }
+    let mut local = secs as i64 + utc_offset_hours as i64 * 3600;
+    if daylight_saving && is_daylight_saving(local) {
+        local += 3600;
+    }
+    Some((local.rem_euclid(SECS_PER_DAY) / 60) as u16)
+}
+
+/// Whether US daylight saving time is in effect at `standard_secs`, seconds since the epoch in
+/// local standard time. It runs from 2:00 on the second Sunday in March to 2:00 (daylight time) on
+/// the first Sunday in November.
+fn is_daylight_saving(standard_secs: i64) -> bool {
+    let year = year_of_day(standard_secs.div_euclid(SECS_PER_DAY));
+    let start = nth_sunday(year, 3, 2) * SECS_PER_DAY + 2 * 3600;
+    let end = nth_sunday(year, 11, 1) * SECS_PER_DAY + 3600;
+    (start..end).contains(&standard_secs)
+}
+
+/// Days since the epoch of the `n`th Sunday in `month` of `year`.
+fn nth_sunday(year: i64, month: i64, n: i64) -> i64 {
+    let first = days_from_civil(year, month, 1);
+    // the epoch was a Thursday
+    let weekday = (first + 4).rem_euclid(7);
+    first + (7 - weekday) % 7 + 7 * (n - 1)
+}
+
+/// Days since the epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year `days` since the epoch falls in.
+fn year_of_day(days: i64) -> i64 {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // years here start in March
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    year_of_era + era * 400 + if month_from_march >= 10 { 1 } else { 0 }
+}
+
+/// A daily span of local time, like `22:00-06:00`, which may run past midnight. `00:00-24:00` is
+/// the whole day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HoursRange {
+    start: u16,
+    end: u16,
+}
+
+impl HoursRange {
+    /// Whether `minute`, since local midnight, is in the range. The end is excluded, so a range
+    /// that ends where it starts is empty.
+    pub fn contains(&self, minute: u16) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseHoursRangeErr;
+
+impl FromStr for HoursRange {
+    type Err = ParseHoursRangeErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once('-').ok_or(ParseHoursRangeErr)?;
+        // only an end of `24:00` is kept as the end of the day, so that `00:00-24:00` isn't empty
+        Ok(Self { start: parse_minutes(start)? % MINUTES_PER_DAY, end: parse_minutes(end)? })
+    }
+}
+
+impl fmt::Display for HoursRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}-{:02}:{:02}", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+    }
+}
+
+/// Minutes since midnight of `HH:MM`, where `24:00` is midnight at the end of the day.
+fn parse_minutes(s: &str) -> Result<u16, ParseHoursRangeErr> {
+    let (hours, minutes) = s.trim().split_once(':').ok_or(ParseHoursRangeErr)?;
+    let hours: u16 = hours.parse().map_err(|_| ParseHoursRangeErr)?;
+    let minutes: u16 = minutes.parse().map_err(|_| ParseHoursRangeErr)?;
+    if hours > 24 || minutes >= 60 {
+        return Err(ParseHoursRangeErr);
+    }
+    match hours * 60 + minutes {
+        total if total <= MINUTES_PER_DAY => Ok(total),
+        _ => Err(ParseHoursRangeErr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_local_minutes() {
+        // 2025-01-15 18:30 UTC, standard time in Seattle
+        assert_eq!(local_minutes(at(1_736_965_800), -8, true), Some(10 * 60 + 30));
+        // 2025-07-15 18:30 UTC, daylight time
+        assert_eq!(local_minutes(at(1_752_604_200), -8, true), Some(11 * 60 + 30));
+        assert_eq!(local_minutes(at(1_752_604_200), -8, false), Some(10 * 60 + 30));
+        // daylight time started 2025-03-09 10:00 UTC and ended 2025-11-02 09:00 UTC
+        assert_eq!(local_minutes(at(1_741_514_340), -8, true), Some(60 + 59));
+        assert_eq!(local_minutes(at(1_741_514_400), -8, true), Some(3 * 60));
+        assert_eq!(local_minutes(at(1_762_073_940), -8, true), Some(60 + 59));
+        assert_eq!(local_minutes(at(1_762_074_000), -8, true), Some(60));
+        assert_eq!(local_minutes(at(60), -8, true), None);
+    }
+
+    #[test]
+    fn test_hours_range() {
+        let night: HoursRange = "22:00-06:30".parse().unwrap();
+        assert_eq!(night.to_string(), "22:00-06:30");
+        assert!(night.contains(23 * 60));
+        assert!(night.contains(6 * 60 + 29));
+        assert!(!night.contains(6 * 60 + 30));
+        assert!(!night.contains(12 * 60));
+
+        let day: HoursRange = "9:00-24:00".parse().unwrap();
+        assert!(day.contains(9 * 60) && !day.contains(0));
+        assert!(!"10:00-10:00".parse::<HoursRange>().unwrap().contains(10 * 60));
+        assert!("25:00-06:00".parse::<HoursRange>().is_err());
+        assert!("1093:00-06:00".parse::<HoursRange>().is_err());
+        assert!("24:30-06:00".parse::<HoursRange>().is_err());
+
+        let all_day: HoursRange = "00:00-24:00".parse().unwrap();
+        assert_eq!(all_day.to_string(), "00:00-24:00");
+        assert!(all_day.contains(0) && all_day.contains(MINUTES_PER_DAY - 1));
+        assert_eq!("24:00-06:00".parse::<HoursRange>().unwrap().to_string(), "00:00-06:00");
+        assert!("22:00".parse::<HoursRange>().is_err());
+    }
+}
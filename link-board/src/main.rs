@@ -1,20 +1,327 @@
 
 #[cfg(not(feature="esp32"))]
-use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, Instant}};
+use std::{cell::RefCell, fs, future, io::{self, BufRead, Write}, sync::{atomic::{AtomicBool, Ordering}, Arc}, thread, time::{Duration, Instant}};
 #[cfg(not(feature="esp32"))]
-use link_board::{data_retriever::dr::get_data_retriever, display, error::Error, spi_adapter};
+use link_board::{
+    calibration::{Calibrator, Command, HELP},
+    config::Config,
+    data_retriever::{dr::get_data_retriever, recording::{Playback, RecordingRetriever, ReplayRetriever}, DataRetriever, TrainSource},
+    display::{self, Brightness, Frame, LinkBoardDisplay, Route, Stage, TrainCache},
+    error::Error,
+    gtfs_rt::get_gtfs_rt_retriever,
+    gtfs_static,
+    layout::{MapLayout, DEFAULT_MAP_LAYOUT},
+    spi_adapter::{self, SpiWriter}
+};
 #[cfg(not(feature="esp32"))]
 use log::{error, info};
+#[cfg(not(feature="esp32"))]
+use tokio::sync::{mpsc, Mutex};
+
+#[cfg(not(feature="esp32"))]
+const USAGE: &str = "\
+usage: link-board
+       link-board validate-layout [LAYOUT_FILE]
+       link-board calibrate [--leds N | --from LAYOUT_FILE] [OUT_FILE]
+       link-board import-gtfs GTFS_ZIP [--between N] [--registry OUT_FILE] [--layout OUT_FILE]
+       link-board replay SESSION_DIR [--speed N | --step]";
+
+/// How often trains are fetched.
+#[cfg(not(feature="esp32"))]
+const REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Shortest wait between the starts of two fetches, whatever the interval asked for.
+#[cfg(not(feature="esp32"))]
+const MIN_FETCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often trains are moved along between fetches.
+#[cfg(not(feature="esp32"))]
+const RENDER_TICK: Duration = Duration::from_secs(1);
+
+/// Layout file written by `calibrate` and `import-gtfs` when no output file is given.
+#[cfg(not(feature="esp32"))]
+const DEFAULT_LAYOUT_OUT: &str = "map-layout.toml";
+
+/// Registry file written by `import-gtfs` when no output file is given, to be copied over
+/// `src/stations/registry.rs`.
+#[cfg(not(feature="esp32"))]
+const DEFAULT_REGISTRY_OUT: &str = "registry.rs";
 
 #[cfg(not(feature="esp32"))]
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     simple_logger::init_with_env()?;
 
-    let prog_start = Instant::now();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => run().await,
+        Some("validate-layout") => validate_layout(args.get(1).map(String::as_str)),
+        Some("calibrate") => match parse_calibrate_args(&args[1..]) {
+            Some((calibrator, out)) => calibrate(calibrator, &out),
+            None => usage(),
+        },
+        Some("replay") => match parse_replay_args(&args[1..]) {
+            Some((session, playback)) => replay(&session, playback).await,
+            None => usage(),
+        },
+        Some("import-gtfs") => match parse_import_args(&args[1..]) {
+            Some(import) => import_gtfs(&import),
+            None => usage(),
+        },
+        Some(_) => usage(),
+    }
+}
+
+#[cfg(not(feature="esp32"))]
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+/// Prints a validation report for the layout file, or the bundled layout if no file is given,
+/// and exits with an error status if the layout has errors.
+#[cfg(not(feature="esp32"))]
+fn validate_layout(path: Option<&str>) -> Result<(), Error> {
+    let layout = match path {
+        Some(path) => MapLayout::from_file(path.as_ref())?,
+        None => MapLayout::from_toml_str(DEFAULT_MAP_LAYOUT)?,
+    };
+
+    let report = layout.validate();
+    println!("{report}");
+    if report.has_errors() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Returns the calibrator to start with and the file to write, or `None` if the args are invalid.
+#[cfg(not(feature="esp32"))]
+fn parse_calibrate_args(args: &[String]) -> Option<(Calibrator, String)> {
+    let mut calibrator = None;
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--leds" if calibrator.is_none() => {
+                calibrator = Some(Calibrator::new(args.next()?.parse().ok().filter(|&n| n > 0)?));
+            },
+            "--from" if calibrator.is_none() => {
+                match MapLayout::from_file(args.next()?.as_ref()) {
+                    Ok(layout) => calibrator = Some(Calibrator::from_layout(&layout)),
+                    Err(e) => {
+                        eprintln!("{e}");
+                        return None;
+                    },
+                }
+            },
+            _ if out.is_none() && !arg.starts_with("--") => out = Some(arg.clone()),
+            _ => return None,
+        }
+    }
+
+    let calibrator = calibrator.unwrap_or_else(|| Calibrator::new(MapLayout::default().total_leds()));
+    Some((calibrator, out.unwrap_or_else(|| DEFAULT_LAYOUT_OUT.to_string())))
+}
+
+/// Lights one LED (or segment) at a time, reading commands from stdin, so the station LEDs of a
+/// new board can be found and written out as a map layout file.
+#[cfg(not(feature="esp32"))]
+fn calibrate(mut calibrator: Calibrator, out: &str) -> Result<(), Error> {
+    let mut adapter = spi_adapter::spi::get_adapter();
+    let mut brightness = Brightness::default();
+    println!("{HELP}");
+
+    let mut stdin = io::stdin().lock();
+    let mut line = String::new();
+    loop {
+        let mut frame = Frame::from(calibrator.frame());
+        brightness.apply(&mut frame);
+        if let Err(e) = adapter.write_rgb(frame.to_vec()) {
+            error!("failed to write LEDs: {e}");
+        }
+        print!("{}> ", calibrator.cursor());
+        io::stdout().flush()?;
+
+        line.clear();
+        if stdin.read_line(&mut line)? == 0 {
+            break;
+        }
+        let command = match Command::parse(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{e}");
+                continue;
+            },
+        };
+
+        match command {
+            Command::Quit => break,
+            Command::Write => match calibrator.to_layout() {
+                Ok(layout) => {
+                    let report = layout.validate();
+                    fs::write(out, layout.to_toml_string())?;
+                    println!("{report}");
+                    println!("wrote {out}");
+                },
+                Err(e) => println!("not written: {e}"),
+            },
+            command => match calibrator.apply(command) {
+                Ok(message) => println!("{message}"),
+                Err(e) => println!("{e}"),
+            },
+        }
+    }
+
+    adapter.clear(calibrator.frame().len());
+    Ok(())
+}
+
+#[cfg(not(feature="esp32"))]
+struct ImportArgs {
+    gtfs_zip: String,
+    leds_between: usize,
+    registry_out: String,
+    layout_out: String,
+}
+
+/// Returns the `import-gtfs` arguments, or `None` if they are invalid.
+#[cfg(not(feature="esp32"))]
+fn parse_import_args(args: &[String]) -> Option<ImportArgs> {
+    let mut gtfs_zip = None;
+    let mut leds_between = 1;
+    let mut registry_out = DEFAULT_REGISTRY_OUT.to_string();
+    let mut layout_out = DEFAULT_LAYOUT_OUT.to_string();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--between" => leds_between = args.next()?.parse().ok()?,
+            "--registry" => registry_out = args.next()?.clone(),
+            "--layout" => layout_out = args.next()?.clone(),
+            _ if gtfs_zip.is_none() && !arg.starts_with("--") => gtfs_zip = Some(arg.clone()),
+            _ => return None,
+        }
+    }
+    Some(ImportArgs { gtfs_zip: gtfs_zip?, leds_between, registry_out, layout_out })
+}
+
+/// Writes a station registry and a starting map layout for the routes in `Config` from a static
+/// GTFS feed, and prints the stations that changed.
+#[cfg(not(feature="esp32"))]
+fn import_gtfs(args: &ImportArgs) -> Result<(), Error> {
+    let config = Config::load()?;
+    let lines = gtfs_static::read_zip(args.gtfs_zip.as_ref(), &config)?;
+    println!("1 Line: {} stations, 2 Line: {} stations", lines.line_1.len(), lines.line_2.len());
+    for station in lines.added() {
+        println!("  added {} ({})", station.name, station.stop_id);
+    }
+    for name in lines.removed() {
+        println!("  removed {name}");
+    }
+
+    fs::write(&args.registry_out, lines.registry_source())?;
+    println!("wrote {}", args.registry_out);
+
+    let layout = lines.layout(args.leds_between);
+    fs::write(&args.layout_out, layout.to_toml_string())?;
+    println!("{}", layout.validate());
+    println!("wrote {}", args.layout_out);
+    Ok(())
+}
+
+#[cfg(not(feature="esp32"))]
+async fn run() -> Result<(), Error> {
+    let config = Config::load()?;
+    let display = display::get_display(spi_adapter::spi::get_adapter(), &config)?;
+    let cache = TrainCache::new(&config).with_position_placement(display.places_by_position());
+
+    if !config.gtfs_rt_feeds().is_empty() {
+        info!("reading trains from {} GTFS-Realtime feed(s)", config.gtfs_rt_feeds().len());
+        render_loop(display, &get_gtfs_rt_retriever(&config), cache, REFRESH_INTERVAL).await
+    } else if let Some(record_dir) = config.record_dir() {
+        let recorder = RecordingRetriever::new(get_data_retriever(&config), record_dir)?;
+        render_loop(display, &recorder, cache, REFRESH_INTERVAL).await
+    } else {
+        render_loop(display, &get_data_retriever(&config), cache, REFRESH_INTERVAL).await
+    }
+}
+
+/// Returns the session to replay and how to play it, or `None` if the args are invalid.
+#[cfg(not(feature="esp32"))]
+fn parse_replay_args(args: &[String]) -> Option<(String, Playback)> {
+    let mut session = None;
+    let mut playback = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" if playback.is_none() => {
+                playback = Some(Playback::Speed(args.next()?.parse().ok().filter(|&speed: &f64| speed > 0.0)?));
+            },
+            "--step" if playback.is_none() => playback = Some(Playback::Step),
+            _ if session.is_none() && !arg.starts_with("--") => session = Some(arg.clone()),
+            _ => return None,
+        }
+    }
+    Some((session?, playback.unwrap_or(Playback::Speed(1.0))))
+}
+
+/// Shows a recorded session on the configured display; the replay sets the pace.
+#[cfg(not(feature="esp32"))]
+async fn replay(session: &str, playback: Playback) -> Result<(), Error> {
+    let config = Config::load()?;
+    let display = display::get_display(spi_adapter::spi::get_adapter(), &config)?;
+    let cache = TrainCache::new(&config).with_position_placement(display.places_by_position());
+    let replay = ReplayRetriever::new(session.as_ref(), playback)?;
+    if playback == Playback::Step {
+        render_loop(display, &StepReplay::new(replay), cache, Duration::ZERO).await
+    } else {
+        render_loop(display, &replay, cache, Duration::ZERO).await
+    }
+}
+
+/// Plays a recording a frame at a time, waiting for enter before each frame after the first.
+#[cfg(not(feature="esp32"))]
+struct StepReplay {
+    replay: ReplayRetriever,
+    enter: Mutex<mpsc::UnboundedReceiver<()>>,
+}
 
-    let mut display = display::get_display(spi_adapter::spi::get_adapter());
-    let data_retriever = get_data_retriever();
+#[cfg(not(feature="esp32"))]
+impl StepReplay {
+    fn new(replay: ReplayRetriever) -> Self {
+        let (enter, enter_rx) = mpsc::unbounded_channel();
+        // stdin is read on its own thread, since a blocking read on the runtime would hold up the
+        // redraws and ctrl-c
+        thread::spawn(move || {
+            let mut line = String::new();
+            while io::stdin().lock().read_line(&mut line).is_ok_and(|read| read > 0) && enter.send(()).is_ok() {
+                line.clear();
+            }
+        });
+        Self { replay, enter: Mutex::new(enter_rx) }
+    }
+}
+
+#[cfg(not(feature="esp32"))]
+impl DataRetriever for StepReplay {
+    async fn get_json_for_all_trains(&self) -> Result<Vec<(Route, String)>, Error> {
+        if let Some(idx) = self.replay.next_frame().filter(|&idx| idx > 0) {
+            println!("press enter for frame {} of {}", idx + 1, self.replay.len());
+            if self.enter.lock().await.recv().await.is_none() {
+                // stdin is closed, so stay on this frame
+                return future::pending().await;
+            }
+        }
+        self.replay.get_json_for_all_trains().await
+    }
+}
+
+/// Fetches trains every `interval` (at least `MIN_FETCH_INTERVAL`) and, separately, redraws them
+/// every `RENDER_TICK` so they move between fetches, until ctrl-c.
+#[cfg(not(feature="esp32"))]
+async fn render_loop(mut display: Box<dyn LinkBoardDisplay>, train_source: &impl TrainSource, cache: TrainCache, interval: Duration) -> Result<(), Error> {
+    let prog_start = Instant::now();
+    let interval = interval.max(MIN_FETCH_INTERVAL);
 
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -27,22 +334,39 @@ async fn main() -> Result<(), Error> {
         r.store(false, Ordering::SeqCst);
     });
 
-    let mut i = 0;
-    let mut wait_time = Instant::now() - Duration::new(15, 0);
-    while running.load(Ordering::SeqCst) {
-        let loop_time = Instant::now();
-
-        if wait_time.elapsed().as_secs() < 15 {
-            continue;
-        } else {
-            wait_time = Instant::now();
+    // both loops run on this task, so the cache is never borrowed by both at once
+    let cache = RefCell::new(cache);
+    let stopped = || async {
+        while running.load(Ordering::SeqCst) {
+            tokio::time::sleep(RENDER_TICK).await;
         }
+    };
+    let fetching = async {
+        let mut i = 0;
+        while running.load(Ordering::SeqCst) {
+            let loop_time = Instant::now();
+            info!("{:?} secs since main loop started.", prog_start.elapsed().as_secs());
+            // a fetch that never returns, like a replay that's over, still stops on ctrl-c
+            let result = tokio::select! {
+                result = train_source.get_all_trains() => result,
+                _ = stopped() => break,
+            };
+            cache.borrow_mut().update(result);
+            info!("i_{} going to sleep after {} seconds", i, loop_time.elapsed().as_secs());
+            i += 1;
 
-        info!("{:?} secs since main loop started.", prog_start.elapsed().as_secs());
-        display::render_trains(&mut display, &data_retriever).await;
-        info!("i_{} going to sleep after {} seconds", i, loop_time.elapsed().as_secs());
-        i += 1;
-    }
+            while running.load(Ordering::SeqCst) && loop_time.elapsed() < interval {
+                tokio::time::sleep(RENDER_TICK.min(interval.saturating_sub(loop_time.elapsed()))).await;
+            }
+        }
+    };
+    let rendering = async {
+        while running.load(Ordering::SeqCst) {
+            display::render_cached_trains(&mut display, &cache.borrow(), Instant::now());
+            tokio::time::sleep(RENDER_TICK).await;
+        }
+    };
+    tokio::join!(fetching, rendering);
 
     info!("clearing LED strip");
     display.clear_trains();
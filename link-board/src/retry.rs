@@ -0,0 +1,139 @@
+//! Retrying failed fetches with exponential backoff, shared by every retriever.
+//!
+//! The sleep is passed in, so this works with tokio on the CLI and with the blocking client on the
+//! ESP32.
+
+use std::{future::Future, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use log::warn;
+
+use crate::{config::Config, error::Error};
+
+/// Longest wait between attempts. A `Retry-After` longer than this isn't waited for; the next
+/// refresh tries again instead.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Per-request timeout, for the retriever to apply to its HTTP client.
+    pub timeout: Duration,
+    /// Attempts after the first one.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each one after it.
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            timeout: config.request_timeout(),
+            max_retries: config.max_retries(),
+            base_delay: config.retry_delay(),
+        }
+    }
+
+    /// How long to wait before retry number `retry` (starting at 0) after `err`, or `None` if it
+    /// shouldn't be retried.
+    pub fn delay(&self, retry: u32, err: &Error) -> Option<Duration> {
+        if retry >= self.max_retries || !err.is_retryable() {
+            return None;
+        }
+        match err.retry_after() {
+            Some(retry_after) if retry_after > MAX_DELAY => None,
+            Some(retry_after) => Some(retry_after),
+            None => Some(self.backoff(retry, jitter())),
+        }
+    }
+
+    /// Exponential backoff, scaled into `[1/2, 1]` of itself by `jitter` in `[0, 1)` so that boards
+    /// restarted together don't retry together.
+    fn backoff(&self, retry: u32, jitter: f64) -> Duration {
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(retry)).min(MAX_DELAY);
+        backoff.mul_f64(0.5 + jitter / 2.0)
+    }
+}
+
+/// Calls `fetch` until it succeeds, fails with an error that isn't worth retrying, or runs out of
+/// retries, waiting between attempts with `sleep`.
+pub async fn retry<T, F, Fut, S, SFut>(policy: &RetryPolicy, mut fetch: F, mut sleep: S) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+    S: FnMut(Duration) -> SFut,
+    SFut: Future<Output = ()>,
+{
+    let mut retry = 0;
+    loop {
+        let err = match fetch().await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        let Some(delay) = policy.delay(retry, &err) else {
+            return Err(err);
+        };
+        warn!("{err}; retrying in {}ms ({} of {})", delay.as_millis(), retry + 1, policy.max_retries);
+        sleep(delay).await;
+        retry += 1;
+    }
+}
+
+/// Parses a `Retry-After` header given in seconds; HTTP dates aren't supported.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Not random, but enough to spread out retries.
+fn jitter() -> f64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+    f64::from(nanos % 1000) / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy { timeout: Duration::from_secs(1), max_retries: 3, base_delay: Duration::from_millis(100) }
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = policy();
+        assert_eq!(policy.backoff(0, 0.5), Duration::from_millis(75));
+        assert_eq!(policy.backoff(2, 0.0), Duration::from_millis(200));
+        assert_eq!(policy.backoff(20, 0.0), MAX_DELAY / 2);
+
+        let server_error = Error::http_status_error(503, "");
+        assert!(policy.delay(2, &server_error).is_some());
+        assert_eq!(policy.delay(3, &server_error), None);
+        assert_eq!(policy.delay(0, &Error::http_status_error(404, "")), None);
+
+        let rate_limited = Error::rate_limited_error("", parse_retry_after(" 7"));
+        assert_eq!(policy.delay(0, &rate_limited), Some(Duration::from_secs(7)));
+        assert_eq!(policy.delay(0, &Error::rate_limited_error("", parse_retry_after("120"))), None);
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let attempts = RefCell::new(0);
+        let slept = RefCell::new(vec![]);
+        let result = retry(
+            &policy(),
+            || {
+                *attempts.borrow_mut() += 1;
+                let result = if *attempts.borrow() < 3 { Err(Error::http_status_error(502, "")) } else { Ok("trains") };
+                std::future::ready(result)
+            },
+            |delay| {
+                slept.borrow_mut().push(delay);
+                std::future::ready(())
+            },
+        ).await;
+
+        assert_eq!(result.unwrap(), "trains");
+        assert_eq!(*attempts.borrow(), 3);
+        assert_eq!(slept.borrow().len(), 2);
+    }
+}
@@ -56,13 +56,17 @@ pub mod spi {
     use log::debug;
     use colored::Colorize;
 
+    #[derive(Default)]
     pub struct SpiAdapter {
+        /// The frame last printed, so redrawing the same trains doesn't print it again.
+        last_frame: Vec<Led>,
     }
 
     impl SpiAdapter {
         pub fn new() -> Self {
             debug!("running anything else");
             Self {
+                last_frame: vec![],
             }
         }
     }
@@ -73,16 +77,21 @@ pub mod spi {
 
     impl SpiWriter for SpiAdapter {
         fn write_rgb(&mut self, rgb_vec: Vec<Led>) -> Result<(), String> {
+            if rgb_vec == self.last_frame {
+                return Ok(());
+            }
             let line = rgb_vec.iter()
                 .map(|rgb| format!("{}", "▊".truecolor(rgb.r(), rgb.g(), rgb.b())))
                 .collect::<Vec<String>>()
                 .join("");
             println!("{}", line);
+            self.last_frame = rgb_vec;
 
             Ok(())
         }
 
         fn clear(&mut self, _num_to_clear: usize) {
+            self.last_frame.clear();
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,130 @@
+//! Every station on the board, and the order each line visits them in.
+//!
+//! Stations are matched to OneBusAway stops by the stop ID of the parent station, which platform
+//! stops reference as their `parent`, falling back to the stop name for stations whose ID isn't
+//! known yet. Names here are the ones used for display and in map layout files.
+
+use crate::{constants::Destination, display::Route};
+
+pub use registry::*;
+
+mod registry;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Station {
+    /// OneBusAway stop ID of the parent station, if known.
+    pub stop_id: Option<&'static str>,
+    pub name: &'static str,
+    /// Stop IDs of the station's platforms that have been seen in OneBusAway data. Feeds that don't
+    /// reference the parent station, like GTFS-Realtime, are matched on these.
+    pub platform_ids: &'static [&'static str],
+}
+
+pub(crate) const fn station(stop_id: &'static str, name: &'static str, platform_ids: &'static [&'static str]) -> Station {
+    Station { stop_id: Some(stop_id), name, platform_ids }
+}
+
+pub(crate) const fn unverified(name: &'static str) -> Station {
+    Station { stop_id: None, name, platform_ids: &[] }
+}
+
+pub fn line(route: Route) -> &'static [Station] {
+    match route {
+        Route::Line1 => &LINE_1,
+        Route::Line2 => &LINE_2,
+    }
+}
+
+/// Stations on `route` in the order trains headed to `destination` visit them.
+pub fn towards(route: Route, destination: Destination) -> impl Iterator<Item = &'static Station> {
+    let stations = line(route).iter();
+    let stations: Box<dyn Iterator<Item = &'static Station>> = match destination {
+        Destination::LynnwoodCC => Box::new(stations),
+        Destination::FederalWayDT | Destination::RedmondDT => Box::new(stations.rev()),
+    };
+    stations
+}
+
+/// Position of `name` on `route`, counting from the far end of the line (Federal Way Downtown or
+/// Downtown Redmond).
+pub fn position(route: Route, name: &str) -> Option<usize> {
+    line(route).iter().position(|s| s.name == name)
+}
+
+pub fn contains(route: Route, name: &str) -> bool {
+    position(route, name).is_some()
+}
+
+/// Finds the station for a OneBusAway stop, preferring its parent station's ID, then its own ID,
+/// then its name.
+pub fn for_stop(stop_id: &str, parent: Option<&str>, name: &str) -> Option<&'static Station> {
+    parent.filter(|p| !p.is_empty())
+        .and_then(for_stop_id)
+        .or_else(|| for_stop_id(stop_id))
+        .or_else(|| all().find(|s| s.name == name))
+}
+
+/// Finds the station for a station or platform stop ID. IDs match with or without the agency
+/// prefix, since GTFS feeds leave off the `40_` that OneBusAway adds.
+pub fn for_stop_id(stop_id: &str) -> Option<&'static Station> {
+    all().find(|s| s.stop_id.is_some_and(|id| same_stop_id(id, stop_id)))
+        .or_else(|| all().find(|s| s.platform_ids.iter().any(|&id| same_stop_id(id, stop_id))))
+}
+
+/// Compares a OneBusAway ID (`40_C03`) to an ID that may be missing the agency prefix (`C03`).
+pub fn same_stop_id(oba_id: &str, id: &str) -> bool {
+    oba_id == id || oba_id.split_once('_').is_some_and(|(_, unprefixed)| unprefixed == id)
+}
+
+/// Number of 2 Line stations, from Downtown Redmond, before the first one it shares with the
+/// 1 Line.
+pub const fn line_2_branch_len() -> usize {
+    let mut i = 0;
+    while i < LINE_2.len() {
+        let mut j = 0;
+        while j < LINE_1.len() {
+            if same_name(LINE_2[i].name, LINE_1[j].name) {
+                return i;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    LINE_2.len()
+}
+
+const fn same_name(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+fn all() -> impl Iterator<Item = &'static Station> {
+    LINE_1.iter().chain(LINE_2.iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_stop() {
+        // platform stops resolve through their parent station, even if renamed
+        assert_eq!(for_stop("40_99913", Some("40_S01"), "Angle Lake Station"), Some(&ANGLE_LAKE));
+        assert_eq!(for_stop("40_X99", None, "Pinehurst"), Some(&PINEHURST));
+        assert_eq!(for_stop("40_X99", Some(""), "Nowhere"), None);
+        assert_eq!(for_stop_id("99903"), Some(&SEATAC));
+        assert_eq!(towards(Route::Line2, Destination::RedmondDT).next(), Some(&LYNNWOOD_CC));
+        assert_eq!(position(Route::Line1, "Pinehurst"), Some(22));
+        assert_eq!(Some(line_2_branch_len()), position(Route::Line2, INTL_DIST.name));
+    }
+}
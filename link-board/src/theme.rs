@@ -0,0 +1,217 @@
+//! The colors each display draws with, by what they mean rather than what they look like.
+//!
+//! A theme file is TOML with colors as `#rrggbb`, gamma encoded at full scale like the rest of
+//! `Led`. Any table left out is taken from the default theme:
+//!
+//! ```toml
+//! empty_station = "#1e1e1e"
+//!
+//! [line_1]
+//! at_station = "#e69f00"
+//! between_stations = "#503800"
+//! ```
+
+use std::{fs, path::Path};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    display::Route,
+    error::{ConfigErr, Error},
+    led::Led
+};
+
+/// Names of the built-in themes, for `Theme::load`.
+pub const BUILT_IN_THEMES: [&str; 3] = ["default", "colorblind", "high-contrast"];
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub line_1: LineColors,
+    pub line_2: LineColors,
+    /// An LED with trains of both lines, or more than one train where the board can tell.
+    pub multiple_trains: LineColors,
+    /// A train that hasn't moved for five minutes, in place of its line's color.
+    pub stalled: Led,
+    /// A station on the map display with no train at it.
+    pub empty_station: Led,
+    /// Trains about to turn back, on the strip and string displays.
+    pub staging: Led,
+    pub buffers: BufferColors,
+    pub status: StatusColors,
+}
+
+/// Colors for trains, depending on whether they're at a station.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct LineColors {
+    pub at_station: Led,
+    pub between_stations: Led,
+}
+
+/// The strip display's buffer LEDs, which mark where each section starts and ends.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct BufferColors {
+    /// Before each line's north side.
+    pub start: Led,
+    /// Between the north and south sides.
+    pub mid: Led,
+    /// After each line's south side.
+    pub end: Led,
+}
+
+/// Colors of the status patterns, see `display::Status`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct StatusColors {
+    pub booting: Led,
+    pub connecting_wifi: Led,
+    pub fetch_failed: Led,
+    pub parse_failed: Led,
+    pub api_key_rejected: Led,
+    /// The status LED while the trains shown are older than the grace period.
+    pub stale: Led,
+}
+
+impl Theme {
+    /// The built-in theme called `name`, or else the theme file at that path.
+    pub fn load(name: &str) -> Result<Self, Error> {
+        match Self::built_in(name) {
+            Some(theme) => Ok(theme),
+            None => Self::from_file(Path::new(name)),
+        }
+    }
+
+    pub fn built_in(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::default()),
+            "colorblind" => Some(Self::colorblind()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let toml = fs::read_to_string(path)?;
+        info!("loaded theme from {}", path.display());
+        Self::from_toml_str(&toml)
+    }
+
+    pub fn from_toml_str(toml: &str) -> Result<Self, Error> {
+        toml::from_str(toml).map_err(|e| Error::config_error(ConfigErr::Toml(e)))
+    }
+
+    pub fn to_toml_string(&self) -> String {
+        toml::to_string(self).expect("theme is always serializable")
+    }
+
+    pub fn line(&self, route: Route) -> LineColors {
+        match route {
+            Route::Line1 => self.line_1,
+            Route::Line2 => self.line_2,
+        }
+    }
+
+    /// Safe for deuteranopia and protanopia: the lines are orange and blue instead of green and
+    /// blue, from the Okabe-Ito palette, and nothing depends on telling red from green.
+    pub fn colorblind() -> Self {
+        Self {
+            line_1: LineColors { at_station: Led::from(230, 159, 0), between_stations: Led::from(80, 56, 0) },
+            line_2: LineColors { at_station: Led::from(0, 114, 178), between_stations: Led::from(0, 40, 62) },
+            multiple_trains: LineColors { at_station: Led::from(240, 228, 66), between_stations: Led::from(84, 80, 23) },
+            stalled: Led::from(213, 94, 0),
+            empty_station: Led::dull_white(),
+            staging: Led::from(92, 54, 75),
+            buffers: BufferColors {
+                start: Led::from(96, 42, 0),
+                mid: Led::from(108, 103, 30),
+                end: Led::from(39, 81, 105),
+            },
+            status: StatusColors {
+                booting: Led::from(96, 42, 0),
+                connecting_wifi: Led::from(0, 51, 80),
+                fetch_failed: Led::from(104, 72, 0),
+                parse_failed: Led::from(92, 54, 75),
+                api_key_rejected: Led::from(96, 42, 0),
+                stale: Led::from(108, 103, 30),
+            },
+        }
+    }
+
+    /// Fully saturated colors at full scale, for boards behind a diffuser or seen from across a
+    /// room.
+    pub fn high_contrast() -> Self {
+        Self {
+            line_1: LineColors { at_station: Led::from(0, 255, 0), between_stations: Led::from(0, 90, 0) },
+            line_2: LineColors { at_station: Led::from(0, 0, 255), between_stations: Led::from(0, 0, 90) },
+            multiple_trains: LineColors { at_station: Led::from(255, 255, 255), between_stations: Led::from(90, 90, 90) },
+            stalled: Led::from(255, 0, 0),
+            empty_station: Led::dull_white(),
+            staging: Led::from(255, 0, 255),
+            buffers: BufferColors {
+                start: Led::from(255, 0, 0),
+                mid: Led::from(255, 160, 0),
+                end: Led::from(0, 160, 255),
+            },
+            status: StatusColors {
+                booting: Led::from(255, 0, 0),
+                connecting_wifi: Led::from(0, 0, 255),
+                fetch_failed: Led::from(255, 160, 0),
+                parse_failed: Led::from(255, 0, 255),
+                api_key_rejected: Led::from(255, 0, 0),
+                stale: Led::from(255, 255, 0),
+            },
+        }
+    }
+}
+
+/// The board's original colors.
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            line_1: LineColors { at_station: Led::ln_1_at_station(), between_stations: Led::ln_1_between_stations() },
+            line_2: LineColors { at_station: Led::ln_2_at_station(), between_stations: Led::ln_2_between_stations() },
+            multiple_trains: LineColors { at_station: Led::at_station_mixed(), between_stations: Led::between_stations_mixed() },
+            stalled: Led::red(),
+            empty_station: Led::empty_station(),
+            staging: Led::purple(),
+            buffers: BufferColors { start: Led::red(), mid: Led::orange(), end: Led::blue() },
+            status: StatusColors {
+                booting: Led::red(),
+                connecting_wifi: Led::blue(),
+                fetch_failed: Led::dull_orange(),
+                parse_failed: Led::dull_purple(),
+                api_key_rejected: Led::red(),
+                stale: Led::orange(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_themes_round_trip() {
+        for name in BUILT_IN_THEMES {
+            let theme = Theme::load(name).unwrap();
+            assert_eq!(Theme::from_toml_str(&theme.to_toml_string()).unwrap(), theme);
+        }
+        assert!(Theme::load("no-such-theme.toml").is_err());
+    }
+
+    #[test]
+    fn test_partial_theme() {
+        let theme = Theme::from_toml_str("empty_station = \"#1e1e1e\"\n[line_1]\nat_station = \"#E69F00\"\nbetween_stations = \"#503800\"\n").unwrap();
+        assert_eq!(theme.line(Route::Line1).at_station, Led::from(230, 159, 0));
+        assert_eq!(theme.empty_station.to_string(), "#1e1e1e");
+        assert_eq!(theme.line_2, Theme::default().line_2);
+
+        assert!(Theme::from_toml_str("[line_1]\nat_station = \"#e69f00\"\n").is_err());
+        assert!(Theme::from_toml_str("staging = \"purple\"\n").is_err());
+        assert!(Theme::from_toml_str("lines = []\n").is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Follows trains from one fetch to the next by vehicle or trip ID, so each train has a history.
+
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use log::{info, warn};
+
+use crate::train::Train;
+
+/// A train that hasn't moved for this long is logged as stalled, and drawn in the theme's stalled
+/// color.
+const STALL_WARNING: Duration = Duration::from_secs(300);
+/// How far, in degrees of latitude or longitude (about 50 m), a train without a next stop has to
+/// go to count as moving. Reported positions wander a little even while a train stands still.
+const MIN_MOVE_DEGREES: f64 = 0.0005;
+
+/// What's known about a train since it was first fetched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainHistory {
+    first_seen: Instant,
+    /// When it was last fetched.
+    last_seen: Instant,
+    last_moved: Instant,
+    /// When the train arrived at the station it's at, if it's at one.
+    arrived_at: Option<Instant>,
+    stations_visited: Vec<String>,
+    /// `(next stop, at station)` when it was last fetched.
+    position: (String, bool),
+    /// Where it was when it last moved, which is how trains without a next stop move.
+    coords: Option<(f64, f64)>,
+}
+
+impl TrainHistory {
+    fn new(train: &Train, now: Instant) -> Self {
+        let at_station = train.at_station();
+        Self {
+            first_seen: now,
+            last_seen: now,
+            last_moved: now,
+            arrived_at: at_station.then_some(now),
+            stations_visited: if at_station { vec![train.next_stop_name.clone()] } else { vec![] },
+            position: (train.next_stop_name.clone(), at_station),
+            coords: train.position(),
+        }
+    }
+
+    pub fn first_seen(&self) -> Instant {
+        self.first_seen
+    }
+
+    /// When the train last changed stations or left or arrived at one.
+    pub fn last_moved(&self) -> Instant {
+        self.last_moved
+    }
+
+    /// Stations the train has been fetched at, oldest first.
+    pub fn stations_visited(&self) -> &[String] {
+        &self.stations_visited
+    }
+
+    /// How long the train has been at its current station, if it's at one.
+    pub fn dwell(&self, now: Instant) -> Option<Duration> {
+        self.arrived_at.map(|arrived_at| now.saturating_duration_since(arrived_at))
+    }
+
+    pub fn stalled_for(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_moved)
+    }
+
+    /// Whether the train had gone `STALL_WARNING` without moving when it was last fetched.
+    pub fn is_stalled(&self) -> bool {
+        self.stalled_for(self.last_seen) >= STALL_WARNING
+    }
+}
+
+/// Matches the trains from each fetch with the ones from the fetch before.
+#[derive(Default)]
+pub struct TrainTracker {
+    trains: HashMap<String, TrainHistory>,
+}
+
+impl TrainTracker {
+    /// Updates the history of every train in `trains`, fetched at `now`, and forgets trains that
+    /// are no longer there. Trains without an ID aren't tracked.
+    pub fn update(&mut self, trains: &[Train], now: Instant) {
+        let mut seen = HashMap::new();
+        for train in trains.iter().filter(|train| train.is_on_active_trip()) {
+            let Some(key) = train.tracking_key() else {
+                continue;
+            };
+            let history = match self.trains.remove(&key) {
+                Some(mut history) => {
+                    track_move(&key, &mut history, train, now);
+                    history
+                },
+                None => TrainHistory::new(train, now),
+            };
+            seen.insert(key, history);
+        }
+        for (key, history) in &self.trains {
+            let seen_for = now.saturating_duration_since(history.first_seen);
+            info!("train {key} is gone after {}s, visited {:?}", seen_for.as_secs(), history.stations_visited);
+        }
+        self.trains = seen;
+    }
+
+    pub fn history(&self, train: &Train) -> Option<&TrainHistory> {
+        self.trains.get(&train.tracking_key()?)
+    }
+}
+
+fn track_move(key: &str, history: &mut TrainHistory, train: &Train, now: Instant) {
+    history.last_seen = now;
+    let position = (train.next_stop_name.clone(), train.at_station());
+    // a train without a next stop keeps the same position, so it's followed by where it is
+    let moved = if train.has_next_stop() {
+        position != history.position
+    } else {
+        moved_from(history.coords, train.position())
+    };
+    if !moved {
+        if history.stalled_for(now) >= STALL_WARNING {
+            let place = if train.has_next_stop() { position.0.as_str() } else { "where it is" };
+            warn!("train {key} hasn't moved from {place} for {}s", history.stalled_for(now).as_secs());
+        }
+        return;
+    }
+
+    if let Some(dwell) = history.dwell(now) {
+        info!("train {key} left {} after {}s", history.position.0, dwell.as_secs());
+    }
+    history.arrived_at = None;
+    if position.1 {
+        history.arrived_at = Some(now);
+        if history.stations_visited.last() != Some(&position.0) {
+            history.stations_visited.push(position.0.clone());
+        }
+    }
+    history.last_moved = now;
+    history.position = position;
+    history.coords = train.position();
+}
+
+/// Whether a train has gone from `from` to `to` by at least `MIN_MOVE_DEGREES`.
+fn moved_from(from: Option<(f64, f64)>, to: Option<(f64, f64)>) -> bool {
+    match (from, to) {
+        (Some((lat, lon)), Some((to_lat, to_lon))) => (to_lat - lat).abs().max((to_lon - lon).abs()) >= MIN_MOVE_DEGREES,
+        (None, Some(_)) => true,
+        (_, None) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{constants::Destination, display::Route, train::TrainIds};
+
+    use super::*;
+
+    fn train(stop: &str, offset: i64) -> Train {
+        let ids = TrainIds { trip_id: "trip".to_string(), vehicle_id: Some("40_14 [322-222]".to_string()), ..Default::default() };
+        Train::new(stop.to_string(), Route::Line1, Destination::LynnwoodCC, offset, offset).with_ids(ids)
+    }
+
+    #[test]
+    fn test_history() {
+        let mut tracker = TrainTracker::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        tracker.update(&[train("Westlake", 0)], at(0));
+        tracker.update(&[train("Westlake", 0)], at(15));
+        let history = tracker.history(&train("Westlake", 0)).unwrap();
+        assert_eq!(history.dwell(at(30)), Some(Duration::from_secs(30)));
+        assert_eq!(history.stalled_for(at(30)), Duration::from_secs(30));
+        assert!(!history.is_stalled());
+
+        tracker.update(&[train("Symphony", 60)], at(45));
+        tracker.update(&[train("Symphony", 0)], at(90));
+        let history = tracker.history(&train("Symphony", 0)).unwrap();
+        assert_eq!(history.first_seen(), at(0));
+        assert_eq!(history.last_moved(), at(90));
+        assert_eq!(history.stations_visited(), ["Westlake", "Symphony"]);
+
+        tracker.update(&[train("Symphony", 0)], at(390));
+        assert!(tracker.history(&train("Symphony", 0)).unwrap().is_stalled());
+
+        tracker.update(&[], at(405));
+        assert!(tracker.history(&train("Symphony", 0)).is_none());
+    }
+
+    #[test]
+    fn test_without_next_stop() {
+        let mut tracker = TrainTracker::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let train = |lat| train("", 1).with_position(Some((lat, -122.3363)));
+
+        // moving along by position, however long it's been since the next stop changed
+        for (i, lat) in [47.6000, 47.6030, 47.6060, 47.6090].into_iter().enumerate() {
+            tracker.update(&[train(lat)], at(150 * i as u64));
+        }
+        assert!(!tracker.history(&train(47.6090)).unwrap().is_stalled());
+
+        // standing still, give or take what GPS reports
+        tracker.update(&[train(47.6091)], at(600));
+        tracker.update(&[train(47.6089)], at(750));
+        assert!(tracker.history(&train(47.6089)).unwrap().is_stalled());
+    }
+}
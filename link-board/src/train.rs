@@ -1,9 +1,15 @@
 use crate::{
-    constants::{Destination, CID, JUDKINS_PARK, LED_OFF, LN_1_STN_NAME_TO_LED_IDX, LN_1_STN_NAME_TO_LED_MAP_IDX, LN_2_STN_NAME_TO_LED_IDX, LN_2_STN_NAME_TO_LED_MAP_IDX},
+    config::Config,
+    constants::{Destination, LED_OFF},
     display::Route,
-    env,
-    led::Led
+    error::Error,
+    layout::MapLayout,
+    led::Led,
+    stations,
+    tracker::TrainHistory
 };
+use std::time::Duration;
+
 use log::{debug, warn};
 
 #[derive(Debug, Clone)]
@@ -13,6 +19,25 @@ pub struct Train {
     destination: Destination,
     next_stop_time_offset: i64,
     closest_stop_time_offset: i64,
+    /// How old the position was when it was fetched, `None` if unknown or only from the schedule.
+    age: Option<Duration>,
+    ids: TrainIds,
+    /// How far the train is between its last stop and `next_stop_name`, from 0 to 1, if known.
+    progress: Option<f64>,
+    /// (latitude, longitude) of the vehicle, if it's reported.
+    position: Option<(f64, f64)>,
+    /// Filled in from the `TrainTracker` when the train is shown.
+    history: Option<TrainHistory>,
+}
+
+/// What OneBusAway or GTFS-Realtime calls the trip and vehicle, to tell trains apart between
+/// fetches.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrainIds {
+    pub trip_id: String,
+    pub vehicle_id: Option<String>,
+    pub active_trip_id: Option<String>,
+    pub block_trip_sequence: Option<u32>,
 }
 
 impl Train {
@@ -28,7 +53,108 @@ impl Train {
             destination,
             next_stop_time_offset,
             closest_stop_time_offset,
+            age: None,
+            ids: TrainIds::default(),
+            progress: None,
+            position: None,
+            history: None,
+        }
+    }
+
+    pub fn with_position(mut self, position: Option<(f64, f64)>) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn position(&self) -> Option<(f64, f64)> {
+        self.position
+    }
+
+    /// Trains OneBusAway reports without a next stop can only be placed by their position.
+    pub fn has_next_stop(&self) -> bool {
+        !self.next_stop_name.is_empty()
+    }
+
+    pub fn with_progress(mut self, progress: Option<f64>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn progress(&self) -> Option<f64> {
+        self.progress
+    }
+
+    pub fn with_ids(mut self, ids: TrainIds) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn ids(&self) -> &TrainIds {
+        &self.ids
+    }
+
+    /// The vehicle ID, without the list of cars that OneBusAway appends in the order they're
+    /// running, or the trip ID if there's no vehicle.
+    pub fn tracking_key(&self) -> Option<String> {
+        let vehicle = self.ids.vehicle_id.as_deref()
+            .map(|id| id.split_once(" [").map_or(id, |(id, _)| id).trim())
+            .filter(|id| !id.is_empty());
+        match vehicle {
+            Some(vehicle) => Some(vehicle.to_string()),
+            None if !self.ids.trip_id.is_empty() => Some(self.ids.trip_id.clone()),
+            None => None,
+        }
+    }
+
+    /// Whether the vehicle is running this trip now, rather than this being a later trip of its
+    /// block.
+    pub fn is_on_active_trip(&self) -> bool {
+        self.ids.active_trip_id.as_ref().map_or(true, |active| *active == self.ids.trip_id)
+    }
+
+    pub fn with_history(mut self, history: Option<TrainHistory>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Whether the tracker has seen the train stay put for a while, see `TrainHistory::is_stalled`.
+    pub fn is_stalled(&self) -> bool {
+        self.history.as_ref().is_some_and(TrainHistory::is_stalled)
+    }
+
+    pub fn with_age(mut self, age: Option<Duration>) -> Self {
+        self.age = age;
+        self
+    }
+
+    pub fn age(&self) -> Option<Duration> {
+        self.age
+    }
+
+    /// Where the train should be `elapsed` after it was fetched, if it keeps to its predicted
+    /// time. It's never moved into its next stop; the next fetch does that. Its position is
+    /// `elapsed` older too.
+    pub fn advanced(&self, elapsed: Duration) -> Self {
+        let mut train = self.clone();
+        train.age = self.age.map(|age| age.saturating_add(elapsed));
+        if self.next_stop_time_offset > 1 {
+            let elapsed_secs = i64::try_from(elapsed.as_secs()).unwrap_or(i64::MAX);
+            train.next_stop_time_offset = self.next_stop_time_offset.saturating_sub(elapsed_secs).max(1);
+            // the rest of the way is covered by the time the train was predicted to take
+            let fraction = (elapsed.as_secs_f64() / self.next_stop_time_offset as f64).min(1.0);
+            train.progress = self.progress.map(|progress| progress + (1.0 - progress) * fraction);
         }
+        train
+    }
+
+    /// Whether the position is older than `Config::max_position_age`, so it's dimmed or dropped.
+    pub fn is_old(&self, config: &Config) -> bool {
+        self.age.is_some_and(|age| age > config.max_position_age())
+    }
+
+    /// Whether the train shouldn't be drawn at all because its position is old.
+    pub fn is_dropped(&self, config: &Config) -> bool {
+        config.drop_old_positions() && self.is_old(config)
     }
 
     pub fn route(&self) -> Route {
@@ -39,19 +165,23 @@ impl Train {
         self.destination
     }
 
-    pub fn get_relative_idx(&self) -> usize {
+    pub fn get_relative_idx(&self, config: &Config) -> Result<usize, Error> {
+        self.relative_idx_on(self.route, config)
+    }
+
+    /// Like `get_relative_idx`, but counting along `route`, for 2 Line trains drawn on the 1 Line
+    /// where they share stations.
+    pub fn relative_idx_on(&self, route: Route, config: &Config) -> Result<usize, Error> {
         debug!("trying to get idx for {:?}", self.next_stop_name.as_str());
-        let raw_idx = match self.route { 
-            // Subtract index of Angle Lake to normalize at 0
-            Route::Line1 => LN_1_STN_NAME_TO_LED_IDX[self.next_stop_name.as_str()] - LN_1_STN_NAME_TO_LED_IDX["Federal Way Downtown"],
-            // Subtract index of South Bellevue to normalize at 0
-            Route::Line2 => LN_2_STN_NAME_TO_LED_IDX[self.next_stop_name.as_str()] - LN_2_STN_NAME_TO_LED_IDX["South Bellevue"],
+        // 0 is Federal Way Downtown on the 1 Line and Downtown Redmond on the 2 Line
+        let Some(raw_idx) = stations::position(route, &self.next_stop_name) else {
+            return Err(Error::unknown_stop_error(self.next_stop_name.clone()));
         };
         debug!("raw_idx {:?}", raw_idx);
         // TODO: figure out logic for not at station, but next station is max or whatever.
         // will probably also need to adjust index logic in main.rs
         // maybe just actually reverse LEDs for southbound?
-        let idx = if env::stations_only() {
+        let idx = if config.stations_only() {
             raw_idx
         } else {
             if self.at_station() {
@@ -71,85 +201,38 @@ impl Train {
         };
         debug!("idx is {:?} because train.at_station is {}, heading ", idx, self.at_station());
     
-        idx
+        Ok(idx)
     }
 
-    pub fn get_map_idx(&self) -> usize {
-        let next_stop_idx = self.next_stop_idx();
-        if self.at_station() {
-            next_stop_idx
-        } else {
-            match self.route {
-                Route::Line1 => next_stop_idx + 1,
-                Route::Line2 => {
-                    if LN_1_STN_NAME_TO_LED_MAP_IDX.contains_key(&self.next_stop_name) {
-                        next_stop_idx + 1
-                    } else {
-                        next_stop_idx - 1
-                    }
-                },
-            }
-        }
+    /// returns the index of the LED immediately before the next stop
+    pub fn idx_before_next_stop(&self, layout: &MapLayout) -> Option<usize> {
+        layout.idx_before_stop(self.route, self.destination, &self.next_stop_name)
     }
 
-    fn next_stop_idx(&self) -> usize {
-        match self.destination {
-            Destination::LynnwoodCC => match self.route {
-                Route::Line1 => LN_1_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].1.0,
-                Route::Line2 => LN_2_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].0.0,
-            },
-            Destination::FederalWayDT => LN_1_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].0.0,
-            Destination::RedmondDT => LN_2_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].1.0,
-        }
+    pub fn at_station(&self) -> bool {
+        self.next_stop_time_offset == 0 && self.closest_stop_time_offset == 0
     }
 
-    /// returns the index of the LED immediately before the next stop
-    pub fn idx_before_next_stop(&self) -> usize {
-        match self.destination {
-            Destination::LynnwoodCC => {
-                match self.route {
-                    Route::Line1 => LN_1_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].1.0 + 1,
-                    Route::Line2 => {
-                        if self.next_stop_name == CID {
-                            let idx = LN_2_STN_NAME_TO_LED_MAP_IDX[JUDKINS_PARK].1.0 + LN_2_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].1.1;
-                            assert_eq!(idx, 254);
-                            idx
-                        } else {
-                            LN_2_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].0.0
-                        }
-                    },
-                }
-            },
-            Destination::FederalWayDT => LN_1_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].0.0 + 1,
-            Destination::RedmondDT => {
-                // Lynnwood to CID, add 1; Judkins Park to Redmond, subtract 1
-                if LN_1_STN_NAME_TO_LED_MAP_IDX.contains_key(&self.next_stop_name) {
-                    LN_2_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].1.0 + 1
-                } else {
-                    LN_2_STN_NAME_TO_LED_MAP_IDX[&self.next_stop_name].1.0 - 1
-                }
-            },
+    /// The train's color, or the stalled color if it's stalled, dimmed if its position is old.
+    pub fn get_led_rgb(&self, config: &Config) -> Led {
+        let led = self.current_led_rgb(config);
+        if self.is_old(config) {
+            led.dimmed()
+        } else {
+            led
         }
     }
 
-    pub fn at_station(&self) -> bool {
-        return self.next_stop_time_offset == 0 && self.closest_stop_time_offset == 0
-    }
-
-    pub fn get_led_rgb(&self) -> Led {
-        if self.at_station() {
-            match self.route {
-                Route::Line1 => Led::ln_1_at_station(),
-                Route::Line2 => Led::ln_2_at_station(),
-            }
+    fn current_led_rgb(&self, config: &Config) -> Led {
+        if self.is_stalled() && (self.at_station() || !config.stations_only()) {
+            config.theme().stalled
+        } else if self.at_station() {
+            config.theme().line(self.route).at_station
         } else {
-            if env::stations_only() {
+            if config.stations_only() {
                 LED_OFF
             } else {
-                match self.route {
-                    Route::Line1 => Led::ln_1_between_stations(),
-                    Route::Line2 => Led::ln_2_between_stations(),
-                }
+                config.theme().line(self.route).between_stations
             }
         }
     }
@@ -161,7 +244,10 @@ impl Train {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Instant;
+
     use super::*;
+    use crate::{constants::CID, tracker::TrainTracker};
 
     #[test]
     fn test_cid_idx_before_next_stop() {
@@ -171,9 +257,61 @@ mod tests {
             destination: Destination::LynnwoodCC,
             next_stop_time_offset: 234,
             closest_stop_time_offset: 2134,
+            age: None,
+            ids: TrainIds::default(),
+            progress: None,
+            position: None,
+            history: None,
         };
 
-        assert_eq!(train.idx_before_next_stop(), 254);
+        assert_eq!(train.idx_before_next_stop(&MapLayout::default()), Some(254));
+    }
+
+    #[test]
+    fn test_advanced() {
+        let train = Train::new(CID.to_string(), Route::Line1, Destination::LynnwoodCC, 100, 100).with_progress(Some(0.5));
+        let advanced = train.advanced(Duration::from_secs(50));
+        assert_eq!((advanced.next_stop_time_offset(), advanced.progress()), (50, Some(0.75)));
+        let arrived = train.advanced(Duration::from_secs(500));
+        assert_eq!((arrived.next_stop_time_offset(), arrived.progress()), (1, Some(1.0)));
+        assert!(!arrived.at_station());
+
+        assert_eq!(advanced.age(), None);
+        let aged = train.with_age(Some(Duration::from_secs(170))).advanced(Duration::from_secs(15));
+        assert_eq!(aged.age(), Some(Duration::from_secs(185)));
+    }
+
+    #[test]
+    fn test_old_positions() {
+        let config = |extra| Config::from_sources(Some(&format!("api_key = \"key\"\nmax_position_age_secs = 60\n{extra}")), |_| None).unwrap();
+        let train = |age| Train::new(CID.to_string(), Route::Line1, Destination::LynnwoodCC, 0, 0).with_age(age);
+
+        let dim = config("");
+        assert!(train(None).get_led_rgb(&dim) == Led::ln_1_at_station());
+        assert!(train(Some(Duration::from_secs(60))).get_led_rgb(&dim) == Led::ln_1_at_station());
+        assert!(train(Some(Duration::from_secs(61))).get_led_rgb(&dim) == Led::ln_1_at_station().dimmed());
+        assert!(!train(Some(Duration::from_secs(61))).is_dropped(&dim));
+
+        let drop = config("drop_old_positions = true\n");
+        assert!(train(Some(Duration::from_secs(61))).is_dropped(&drop));
+        assert!(!train(None).is_dropped(&drop));
+    }
+
+    #[test]
+    fn test_stalled() {
+        let config = Config::from_sources(Some("api_key = \"key\"\n"), |_| None).unwrap();
+        let ids = TrainIds { trip_id: "trip".to_string(), ..Default::default() };
+        let train = Train::new(CID.to_string(), Route::Line1, Destination::LynnwoodCC, 0, 0).with_ids(ids);
+        let mut tracker = TrainTracker::default();
+        let start = Instant::now();
+
+        tracker.update(std::slice::from_ref(&train), start);
+        let moving = train.clone().with_history(tracker.history(&train).cloned());
+        assert!(moving.get_led_rgb(&config) == Led::ln_1_at_station());
+
+        tracker.update(std::slice::from_ref(&train), start + Duration::from_secs(300));
+        let stalled = train.clone().with_history(tracker.history(&train).cloned());
+        assert!(stalled.get_led_rgb(&config) == config.theme().stalled);
     }
 
 }
\ No newline at end of file
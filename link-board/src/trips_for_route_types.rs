@@ -3,12 +3,19 @@ use serde::Deserialize;
 #[derive(Deserialize)]
 #[serde(rename_all="camelCase")]
 pub struct TripsForRoute {
-    pub data: Data,
+    /// OneBusAway's status code, 200 on success
+    pub code: Option<i64>,
+    /// Server time of the response, in Unix millis
+    pub current_time: Option<i64>,
+    pub text: Option<String>,
+    pub data: Option<Data>,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all="camelCase")]
 pub struct Data {
+    #[serde(default)]
+    pub limit_exceeded: bool,
     pub list: Vec<TripDetails>,
     pub references: References,
 }
@@ -18,15 +25,53 @@ pub struct Data {
 pub struct TripDetails {
     pub trip_id: String,
     pub status: Option<TripStatus>,
+    /// Only there with `includeSchedule=true`
+    pub schedule: Option<TripSchedule>,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all="camelCase")]
 pub struct TripStatus {
+    /// The trip the vehicle is on now, which is a different one if this trip hasn't started yet
+    pub active_trip_id: Option<String>,
+    pub block_trip_sequence: Option<u32>,
+    pub vehicle_id: Option<String>,
     pub scheduled_distance_along_trip: Option<f64>,
+    /// Meters from the start of the trip, from real-time data if there is any
+    pub distance_along_trip: Option<f64>,
     pub next_stop: Option<String>,
     pub next_stop_time_offset: Option<i64>,
     pub closest_stop_time_offset: i64,
+    /// Unix millis of the last real-time update for the trip, 0 if there hasn't been one
+    pub last_update_time: Option<i64>,
+    /// Unix millis of the last reported vehicle location, 0 if there hasn't been one
+    pub last_location_update_time: Option<i64>,
+    /// Whether the status is from real-time data rather than the schedule
+    #[serde(default)]
+    pub predicted: bool,
+    /// Where the vehicle is now, estimated from its last known location if there is one
+    pub position: Option<LatLon>,
+    pub last_known_location: Option<LatLon>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct LatLon {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct TripSchedule {
+    pub stop_times: Vec<ScheduleStopTime>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct ScheduleStopTime {
+    pub stop_id: String,
+    /// Meters from the start of the trip
+    pub distance_along_trip: f64,
 }
 
 #[derive(Deserialize)]
@@ -41,6 +86,7 @@ pub struct References {
 pub struct Stop {
     pub name: String,
     pub id: String,
+    pub parent: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -0,0 +1,119 @@
+//! A scripted OneBusAway `trips-for-route` server on localhost.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream}
+};
+
+/// A scripted response.
+#[derive(Clone, Debug)]
+pub struct Reply {
+    pub status: u16,
+    pub body: String,
+    /// How long to wait before responding.
+    pub delay: Duration,
+}
+
+impl Reply {
+    pub fn ok(body: &str) -> Self {
+        Self::status(200, body)
+    }
+
+    pub fn status(status: u16, body: &str) -> Self {
+        Self { status, body: body.to_string(), delay: Duration::ZERO }
+    }
+
+    pub fn delayed(self, delay: Duration) -> Self {
+        Self { delay, ..self }
+    }
+}
+
+#[derive(Default)]
+struct Script {
+    /// Replies per route ID, played in order; the last one repeats.
+    replies: HashMap<String, VecDeque<Reply>>,
+    /// Request paths, including the query string.
+    requests: Vec<String>,
+}
+
+pub struct MockOba {
+    addr: SocketAddr,
+    script: Arc<Mutex<Script>>,
+}
+
+impl MockOba {
+    /// Binds to a free localhost port and serves until the test's runtime shuts down.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let script = Arc::new(Mutex::new(Script::default()));
+
+        let server_script = script.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(respond(stream, server_script.clone()));
+            }
+        });
+        Self { addr, script }
+    }
+
+    /// Value for the `oba_base_url` config key.
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn push(&self, route_id: &str, reply: Reply) {
+        self.script.lock().unwrap().replies.entry(route_id.to_string()).or_default().push_back(reply);
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.script.lock().unwrap().requests.clone()
+    }
+}
+
+async fn respond(stream: TcpStream, script: Arc<Mutex<Script>>) {
+    let mut stream = BufReader::new(stream);
+    let mut request_line = String::new();
+    if stream.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+        return;
+    }
+    // headers aren't needed, but have to be read before responding
+    let mut header = String::new();
+    while stream.read_line(&mut header).await.unwrap_or(0) > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+    let route_id = path.strip_prefix("/api/where/trips-for-route/")
+        .and_then(|rest| rest.split_once(".json"))
+        .map(|(route_id, _)| route_id.to_string())
+        .unwrap_or_default();
+
+    let reply = {
+        let mut script = script.lock().unwrap();
+        script.requests.push(path);
+        match script.replies.get_mut(&route_id) {
+            Some(replies) if replies.len() > 1 => replies.pop_front(),
+            Some(replies) => replies.front().cloned(),
+            None => None,
+        }
+    };
+    let reply = reply.unwrap_or_else(|| Reply::status(404, r#"{"code":404,"text":"resource not found"}"#));
+
+    tokio::time::sleep(reply.delay).await;
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.status,
+        reply.body.len(),
+        reply.body
+    );
+    let _ = stream.get_mut().write_all(response.as_bytes()).await;
+    let _ = stream.get_mut().shutdown().await;
+}
@@ -0,0 +1,209 @@
+//! Renders trains through the real OneBusAway retriever against `MockOba`.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant}
+};
+
+use link_board::{
+    config::Config,
+    data_retriever::{dr::get_data_retriever, DataRetriever},
+    display::{self, LinkBoardDisplay, TrainCache},
+    led::Led,
+    spi_adapter::SpiWriter
+};
+use mock_oba::{MockOba, Reply};
+
+mod mock_oba;
+#[path = "../src/test_data.rs"]
+mod test_data;
+
+const LINE_1_ROUTE_ID: &str = "40_100479";
+const LINE_2_ROUTE_ID: &str = "40_2LINE";
+
+/// Keeps every frame written to the LEDs.
+#[derive(Clone, Default)]
+struct Frames(Arc<Mutex<Vec<Vec<Led>>>>);
+
+impl Frames {
+    fn count(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    /// Number of LEDs in the last frame showing a train.
+    fn trains_in_last(&self) -> usize {
+        self.in_last(&[Led::ln_1_at_station(), Led::ln_1_between_stations(), Led::ln_2_at_station(), Led::ln_2_between_stations()])
+    }
+
+    /// Number of LEDs in the last frame with any of `colors`.
+    fn in_last(&self, colors: &[Led]) -> usize {
+        self.0.lock().unwrap().last().map_or(0, |frame| frame.iter().filter(|led| colors.contains(led)).count())
+    }
+}
+
+impl SpiWriter for Frames {
+    fn write_rgb(&mut self, rgb_vec: Vec<Led>) -> Result<(), String> {
+        self.0.lock().unwrap().push(rgb_vec);
+        Ok(())
+    }
+
+    fn clear(&mut self, _num_to_clear: usize) {}
+}
+
+/// Config for the map display against `server`, with any `extra` TOML. Frames are written as drawn,
+/// so they can be compared with `Led` colors.
+fn config(server: &MockOba, extra: &str) -> Config {
+    let toml = format!("api_key = \"test\"\ndisplay_type = 2\nbrightness = 1.0\ngamma = 1.0\noba_base_url = \"{}\"\n{extra}", server.base_url());
+    Config::from_sources(Some(&toml), |_| None).unwrap()
+}
+
+fn map_display(config: &Config) -> (Box<dyn LinkBoardDisplay>, Frames) {
+    let frames = Frames::default();
+    (display::get_display(frames.clone(), config).unwrap(), frames)
+}
+
+#[tokio::test]
+async fn test_renders_trains_from_server() {
+    let server = MockOba::start().await;
+    server.push(LINE_1_ROUTE_ID, Reply::ok(test_data::TEST_LARGE_PAYLOAD_1_LINE));
+    server.push(LINE_2_ROUTE_ID, Reply::ok(test_data::TEST_LARGE_PAYLOAD_2_LINE));
+    let config = config(&server, "");
+    let (mut display, frames) = map_display(&config);
+
+    display::render_trains(&mut display, &get_data_retriever(&config), &mut TrainCache::new(&config)).await;
+
+    assert_eq!(frames.count(), 2);
+    assert!(frames.trains_in_last() > 0);
+    assert!(display.last_frame().unwrap().leds() == frames.0.lock().unwrap().last().unwrap().as_slice());
+    let mut requests = server.requests();
+    requests.sort();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].starts_with("/api/where/trips-for-route/40_100479.json?"));
+    assert!(requests[1].starts_with("/api/where/trips-for-route/40_2LINE.json?"));
+    assert!(requests.iter().all(|r| r.ends_with("&key=test")));
+}
+
+#[tokio::test]
+async fn test_bad_responses_without_data_show_status() {
+    let server = MockOba::start().await;
+    server.push(LINE_1_ROUTE_ID, Reply::ok("{\"code\":200,\"data\":"));
+    server.push(LINE_1_ROUTE_ID, Reply::status(500, "Internal Server Error"));
+    server.push(LINE_2_ROUTE_ID, Reply::ok(test_data::TEST_LARGE_PAYLOAD_2_LINE));
+    let config = config(&server, "max_retries = 0\n");
+    let (mut display, frames) = map_display(&config);
+    let mut cache = TrainCache::new(&config);
+
+    for pattern in [Led::dull_purple(), Led::dull_orange()] {
+        display::render_trains(&mut display, &get_data_retriever(&config), &mut cache).await;
+        assert_eq!(frames.trains_in_last(), 0);
+        assert!(frames.in_last(&[pattern]) > 0);
+    }
+    assert_eq!(frames.count(), 3);
+}
+
+#[tokio::test]
+async fn test_rejected_api_key_shows_status() {
+    let server = MockOba::start().await;
+    server.push(LINE_1_ROUTE_ID, Reply::ok(r#"{"code":401,"text":"permission denied"}"#));
+    let config = config(&server, "routes = [1]\n");
+    let (mut display, frames) = map_display(&config);
+
+    display::render_trains(&mut display, &get_data_retriever(&config), &mut TrainCache::new(&config)).await;
+    let shown = frames.0.lock().unwrap();
+    assert!(shown[1].iter().step_by(2).all(|&led| led == Led::red()));
+    assert!(shown[1].iter().skip(1).step_by(2).all(|&led| led == Led::off()));
+}
+
+/// Renders twice with one cache: once from a good response, then after a failed request.
+async fn render_success_then_failure(extra: &str) -> Frames {
+    let server = MockOba::start().await;
+    server.push(LINE_1_ROUTE_ID, Reply::ok(test_data::TEST_LARGE_PAYLOAD_1_LINE));
+    server.push(LINE_1_ROUTE_ID, Reply::status(500, "Internal Server Error"));
+    let config = config(&server, &format!("routes = [1]\nmax_retries = 0\n{extra}"));
+    let (mut display, frames) = map_display(&config);
+    let mut cache = TrainCache::new(&config);
+
+    for _ in 0..2 {
+        display::render_trains(&mut display, &get_data_retriever(&config), &mut cache).await;
+    }
+    assert_eq!(frames.count(), 3);
+    frames
+}
+
+#[tokio::test]
+async fn test_failures_keep_last_trains_through_grace_period() {
+    let frames = render_success_then_failure("").await;
+    assert!(frames.trains_in_last() > 0);
+    let shown = frames.0.lock().unwrap();
+    assert!(shown[1] == shown[2]);
+}
+
+#[tokio::test]
+async fn test_failures_after_grace_period_dim_last_trains() {
+    let frames = render_success_then_failure("grace_period_secs = 0\n").await;
+    assert_eq!(frames.trains_in_last(), 0);
+    assert!(frames.in_last(&[Led::ln_1_at_station().dimmed(), Led::ln_1_between_stations().dimmed()]) > 0);
+}
+
+#[tokio::test]
+async fn test_failures_after_no_data_period_show_no_data() {
+    let frames = render_success_then_failure("grace_period_secs = 0\nno_data_after_secs = 0\n").await;
+    assert_eq!(frames.trains_in_last(), 0);
+    assert!(frames.in_last(&[Led::dull_orange()]) > 0);
+}
+
+#[tokio::test]
+async fn test_slow_responses_are_waited_for() {
+    let server = MockOba::start().await;
+    let delay = Duration::from_millis(200);
+    server.push(LINE_1_ROUTE_ID, Reply::ok(test_data::TEST_LARGE_PAYLOAD_1_LINE).delayed(delay));
+    server.push(LINE_2_ROUTE_ID, Reply::ok(test_data::TEST_LARGE_PAYLOAD_2_LINE).delayed(delay));
+    let config = config(&server, "");
+    let (mut display, frames) = map_display(&config);
+
+    let start = Instant::now();
+    display::render_trains(&mut display, &get_data_retriever(&config), &mut TrainCache::new(&config)).await;
+    assert!(start.elapsed() >= delay);
+    assert!(frames.trains_in_last() > 0);
+}
+
+#[tokio::test]
+async fn test_failed_requests_are_retried() {
+    let server = MockOba::start().await;
+    server.push(LINE_1_ROUTE_ID, Reply::status(503, "Service Unavailable"));
+    server.push(LINE_1_ROUTE_ID, Reply::status(429, "slow down"));
+    server.push(LINE_1_ROUTE_ID, Reply::ok(test_data::TEST_LARGE_PAYLOAD_1_LINE));
+    let config = config(&server, "routes = [1]\nretry_delay_ms = 10\n");
+
+    let results = get_data_retriever(&config).get_json_for_all_trains().await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_hung_requests_time_out() {
+    let server = MockOba::start().await;
+    server.push(LINE_1_ROUTE_ID, Reply::ok(test_data::TEST_LARGE_PAYLOAD_1_LINE).delayed(Duration::from_secs(5)));
+    let config = config(&server, "routes = [1]\nrequest_timeout_ms = 100\nmax_retries = 1\nretry_delay_ms = 10\n");
+
+    let start = Instant::now();
+    assert!(get_data_retriever(&config).get_json_for_all_trains().await.is_err());
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn test_http_errors_include_status_and_body() {
+    let server = MockOba::start().await;
+    server.push(LINE_1_ROUTE_ID, Reply::status(401, r#"{"code":401,"text":"permission denied"}"#));
+    server.push(LINE_2_ROUTE_ID, Reply::status(429, "slow down"));
+    let config = |server, extra: &str| config(server, &format!("max_retries = 0\n{extra}"));
+
+    let err = get_data_retriever(&config(&server, "routes = [1]\n")).get_json_for_all_trains().await.unwrap_err();
+    assert_eq!(err.to_string(), r#"HTTP status 401: {"code":401,"text":"permission denied"}"#);
+
+    let err = get_data_retriever(&config(&server, "routes = [2]\n")).get_json_for_all_trains().await.unwrap_err();
+    assert_eq!(err.to_string(), "rate limited: slow down");
+
+    assert!(get_data_retriever(&config(&server, "")).get_json_for_all_trains().await.is_err());
+}